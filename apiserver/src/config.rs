@@ -31,4 +31,9 @@ pub struct Config {
     /// The Server port.
     #[clap(long, env = "AMP_PORT")]
     pub port: u16,
+
+    /// The name of the Kubernetes namespace that Amphitheatre is
+    /// currently running in, the default is `amp-system`
+    #[clap(long, env = "AMP_NAMESPACE", default_value = "amp-system")]
+    pub namespace: String,
 }
@@ -18,16 +18,17 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use axum::extract::{Path, State};
+use axum::http::StatusCode;
 use axum::response::sse::{Event, KeepAlive};
 use axum::response::{IntoResponse, Sse};
+use axum::Json;
 use futures::Stream;
-use k8s_openapi::api::core::v1::Pod;
-use kube::api::LogParams;
-use kube::Api;
+use serde_json::json;
 use tokio_stream::StreamExt as _;
 use uuid::Uuid;
 
 use crate::context::Context;
+use crate::requests::actor::ScaleActorRequest;
 use crate::response::{data, ApiError};
 use crate::services::actor::ActorService;
 
@@ -82,27 +83,22 @@ pub async fn detail(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Re
     tag = "Actors"
 )]
 pub async fn logs(
-    Path(_id): Path<Uuid>,
+    Path(id): Path<Uuid>,
     State(ctx): State<Arc<Context>>,
-) -> Sse<impl Stream<Item = axum::response::Result<Event, Infallible>>> {
-    let api: Api<Pod> = Api::namespaced(ctx.k8s.clone(), "default");
-    let params = LogParams::default();
-
-    let stream = api
-        .log_stream("getting-started", &params)
-        .await
-        .unwrap()
+) -> Result<Sse<impl Stream<Item = axum::response::Result<Event, Infallible>>>, ApiError> {
+    let stream = ActorService::logs(ctx, id)
+        .await?
         .map(|result| match result {
-            Ok(line) => Event::default().data(String::from_utf8_lossy(&line)),
-            Err(err) => Event::default().event("error").data(err.to_string()),
+            Ok(line) => Event::default().data(line),
+            Err(err) => Event::default().event("error").data(err),
         })
         .map(Ok)
         .throttle(Duration::from_secs(1));
 
-    Sse::new(stream).keep_alive(KeepAlive::default())
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
 
-/// Returns a actor's info, including environments, volumes...
+/// Returns a actor's info, including environments, volumes, autoscaling...
 #[utoipa::path(
     get, path = "/v1/actors/{id}/info",
     params(
@@ -114,11 +110,13 @@ pub async fn logs(
     ),
     tag = "Actors"
 )]
-pub async fn info(Path(_id): Path<Uuid>) -> Result<impl IntoResponse, ApiError> {
+pub async fn info(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Result<impl IntoResponse, ApiError> {
+    let autoscaling = ActorService::autoscaling(ctx, id).await?;
+
     Ok(data(HashMap::from([
         (
             "environments",
-            HashMap::from([
+            json!(HashMap::from([
                 ("K3S_TOKEN", "RdqNLMXRiRsHJhmxKurR"),
                 ("K3S_KUBECONFIG_OUTPUT", "/output/kubeconfig.yaml"),
                 (
@@ -126,11 +124,11 @@ pub async fn info(Path(_id): Path<Uuid>) -> Result<impl IntoResponse, ApiError>
                     "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin:/bin/aux",
                 ),
                 ("CRI_CONFIG_FILE", "/var/lib/rancher/k3s/agent/etc/crictl.yaml"),
-            ]),
+            ])),
         ),
         (
             "mounts",
-            HashMap::from([
+            json!(HashMap::from([
                 (
                     "/VAR/LIB/CNI",
                     "/var/lib/docker/volumes/00f49631b07ccd74de44d3047d5f889395ac871e05b622890b6dd788d34a59f4/_data",
@@ -147,9 +145,10 @@ pub async fn info(Path(_id): Path<Uuid>) -> Result<impl IntoResponse, ApiError>
                     "/VAR/LOG",
                     "/var/lib/docker/volumes/f64c2f2cf81cfde89879f2a17924b31bd2f2e6a6a738f7df949bf6bd57102d25/_data",
                 ),
-            ]),
+            ])),
         ),
-        ("port", HashMap::from([("6443/tcp", "0.0.0.0:42397")])),
+        ("port", json!(HashMap::from([("6443/tcp", "0.0.0.0:42397")]))),
+        ("autoscaling", json!(autoscaling)),
     ])))
 }
 
@@ -173,3 +172,48 @@ pub async fn stats(Path(_id): Path<Uuid>) -> Result<impl IntoResponse, ApiError>
         ("NETWORK I/O", "5.7 kB / 3 kB"),
     ])))
 }
+
+/// Cancel the in-flight build of actor.
+#[utoipa::path(
+    post, path = "/v1/actors/{id}/actions/cancel",
+    params(
+        ("id" = Uuid, description = "The id of actor"),
+    ),
+    responses(
+        (status = 204, description = "Actor's build cancelled successfully"),
+        (status = 400, description = "Actor is not building"),
+        (status = 404, description = "Actor not found"),
+        (status = 500, description = "Internal Server Error"),
+    ),
+    tag = "Actors"
+)]
+pub async fn cancel(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Result<impl IntoResponse, ApiError> {
+    ActorService::cancel(ctx, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Scale the actor to the number of pods.
+#[utoipa::path(
+    post, path = "/v1/actors/{id}/actions/scale",
+    params(
+        ("id" = Uuid, description = "The id of actor"),
+    ),
+    request_body = ScaleActorRequest,
+    responses(
+        (status = 204, description = "Actor scaled successfully"),
+        (status = 400, description = "The number of replicas is invalid"),
+        (status = 404, description = "Actor not found"),
+        (status = 500, description = "Internal Server Error"),
+    ),
+    tag = "Actors"
+)]
+pub async fn scale(
+    Path(id): Path<Uuid>,
+    State(ctx): State<Arc<Context>>,
+    Json(req): Json<ScaleActorRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    ActorService::scale(ctx, id, req.replicas).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -14,3 +14,4 @@
 
 pub mod actor;
 pub mod playbook;
+pub mod webhook;
@@ -30,7 +30,7 @@ use tokio_stream::StreamExt as _;
 use uuid::Uuid;
 
 use crate::context::Context;
-use crate::requests::playbook::{CreatePlaybookRequest, UpdatePlaybookRequest};
+use crate::requests::playbook::{CreatePlaybookRequest, SetSecretRequest, UpdatePlaybookRequest};
 use crate::response::{data, ApiError};
 use crate::services::playbook::PlaybookService;
 
@@ -134,6 +134,35 @@ pub async fn delete(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Re
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Set a runtime secret of playbook, it takes precedence over the platform one with the same name.
+#[utoipa::path(
+    put, path = "/v1/playbooks/{id}/secrets/{name}",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+        ("name" = String, description = "The name of secret"),
+    ),
+    request_body(
+        content = inline(SetSecretRequest),
+        description = "Set secret request",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 204, description = "Secret set successfully"),
+        (status = 400, description = "The name of secret is invalid"),
+        (status = 404, description = "Playbook not found")
+    ),
+    tag = "Playbooks"
+)]
+pub async fn secret(
+    Path((id, name)): Path<(Uuid, String)>,
+    State(ctx): State<Arc<Context>>,
+    Json(payload): Json<SetSecretRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    PlaybookService::secret(ctx, id, &name, payload.data).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Output the event streams of playbook
 #[utoipa::path(
     get, path = "/v1/playbooks/{id}/events",
@@ -204,3 +233,22 @@ pub async fn stop(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Resu
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Update a playbook, re-resolve its actors to the latest revisions.
+#[utoipa::path(
+    post, path = "/v1/playbooks/{id}/actions/update",
+    params(
+        ("id" = Uuid, description = "The id of playbook"),
+    ),
+    responses(
+        (status = 204, description = "Playbook updated successfully"),
+        (status = 404, description = "Playbook not found"),
+        (status = 500, description = "Internal Server Error"),
+    ),
+    tag = "Playbooks",
+)]
+pub async fn refresh(Path(id): Path<Uuid>, State(ctx): State<Arc<Context>>) -> Result<impl IntoResponse, ApiError> {
+    PlaybookService::refresh(ctx, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
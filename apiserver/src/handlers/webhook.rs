@@ -0,0 +1,52 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+
+use crate::context::Context;
+use crate::requests::webhook::Provider;
+use crate::response::{data, ApiError};
+use crate::services::webhook::WebhookService;
+
+// The Webhooks Service Handlers.
+// See [API Documentation: webhook](https://docs.amphitheatre.app/api/webhook)
+
+/// Receive the push events of Git providers, and rebuild the actors tracking
+/// the pushed branch.
+#[utoipa::path(
+    post, path = "/v1/webhooks/{provider}",
+    params(
+        ("provider" = String, description = "The Git provider, one of github, gitlab and gitea"),
+    ),
+    responses(
+        (status = 200, description = "Webhook received successfully", body = [String]),
+        (status = 400, description = "Bad Request"),
+        (status = 401, description = "Unauthorized"),
+    ),
+    tag = "Webhooks"
+)]
+pub async fn receive(
+    Path(provider): Path<Provider>,
+    State(ctx): State<Arc<Context>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, ApiError> {
+    let actors = WebhookService::receive(ctx, provider, &headers, &body).await?;
+    Ok(data(actors))
+}
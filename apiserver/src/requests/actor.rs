@@ -8,3 +8,9 @@ pub struct CreatePlaybookRequest {
     pub description: String,
     pub preface: Source,
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ScaleActorRequest {
+    /// The number of pods of actor.
+    pub replicas: i32,
+}
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+
 use amp_common::schema::Source;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::ToSchema;
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -21,6 +24,16 @@ pub struct CreatePlaybookRequest {
     pub title: String,
     pub description: String,
     pub preface: Source,
+    /// The values for the `${var}` placeholders in actor manifests.
+    pub variables: Option<HashMap<String, String>>,
+    /// The partial actor specs merged onto the resolved actors, keyed by actor name.
+    #[schema(value_type = Option<Object>)]
+    pub overrides: Option<HashMap<String, Value>>,
+    /// Refuse to move the actors to newer commits than the locked ones,
+    /// until they are updated explicitly.
+    pub locked: Option<bool>,
+    /// The repository for the build cache of actors in this playbook.
+    pub cache_repo: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -28,3 +41,9 @@ pub struct UpdatePlaybookRequest {
     pub title: Option<String>,
     pub description: Option<String>,
 }
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SetSecretRequest {
+    /// The values of secret keyed by their names, they replace the existing ones.
+    pub data: HashMap<String, String>,
+}
@@ -0,0 +1,393 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::schema::Source;
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::response::ApiError;
+
+/// The Git providers that webhooks are received from.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Github,
+    Gitlab,
+    Gitea,
+}
+
+/// The push payload, only the fields in common of providers are read.
+#[derive(Debug, Deserialize)]
+struct PushPayload {
+    #[serde(rename = "ref")]
+    reference: String,
+    after: String,
+    /// The repository of GitHub and Gitea.
+    repository: Option<Repository>,
+    /// The repository of GitLab.
+    project: Option<Repository>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Repository {
+    clone_url: Option<String>,
+    html_url: Option<String>,
+    ssh_url: Option<String>,
+    git_http_url: Option<String>,
+    git_ssh_url: Option<String>,
+    web_url: Option<String>,
+}
+
+/// The push event normalized from the payloads of providers.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PushEvent {
+    /// The addresses of the pushed repository.
+    pub repos: Vec<String>,
+    /// The pushed branch.
+    pub branch: String,
+    /// The commit SHA of the branch after the push.
+    pub rev: String,
+}
+
+impl PushEvent {
+    /// Verify the signature of webhook with the secret, and parse the push event
+    /// from payload. Returns none if it's not a push to a branch, e.g. ping.
+    pub fn parse(provider: Provider, headers: &HeaderMap, body: &[u8], secret: &str) -> Result<Option<Self>, ApiError> {
+        verify(provider, headers, body, secret)?;
+
+        let (name, expected) = match provider {
+            Provider::Github => ("X-GitHub-Event", "push"),
+            Provider::Gitlab => ("X-Gitlab-Event", "Push Hook"),
+            Provider::Gitea => ("X-Gitea-Event", "push"),
+        };
+        if header(headers, name) != Some(expected) {
+            return Ok(None);
+        }
+
+        let payload: PushPayload = serde_json::from_slice(body).map_err(|_| ApiError::BadRequest)?;
+
+        // Ignore the pushed tags and the deleted branches.
+        let branch = match payload.reference.strip_prefix("refs/heads/") {
+            Some(branch) => branch.to_string(),
+            None => return Ok(None),
+        };
+        if payload.after.chars().all(|c| c == '0') {
+            return Ok(None);
+        }
+
+        let repository = payload.repository.or(payload.project).unwrap_or_default();
+        let repos = [
+            repository.clone_url,
+            repository.html_url,
+            repository.ssh_url,
+            repository.git_http_url,
+            repository.git_ssh_url,
+            repository.web_url,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        Ok(Some(Self {
+            repos,
+            branch,
+            rev: payload.after,
+        }))
+    }
+
+    /// Check if the source is tracking the pushed branch.
+    pub fn matches(&self, source: &Source) -> bool {
+        if source.tag.is_some() || source.branch.as_deref() != Some(self.branch.as_str()) {
+            return false;
+        }
+
+        let repo = normalize(&source.repo);
+        self.repos.iter().any(|r| normalize(r) == repo)
+    }
+}
+
+/// Verify the webhook is signed with the secret.
+fn verify(provider: Provider, headers: &HeaderMap, body: &[u8], secret: &str) -> Result<(), ApiError> {
+    match provider {
+        Provider::Github => {
+            let signature = header(headers, "X-Hub-Signature-256").and_then(|v| v.strip_prefix("sha256="));
+            verify_hmac(secret, body, signature)
+        }
+        Provider::Gitea => verify_hmac(secret, body, header(headers, "X-Gitea-Signature")),
+        Provider::Gitlab => {
+            // GitLab sends the secret token as it is, instead of signing the payload.
+            let token = header(headers, "X-Gitlab-Token").unwrap_or_default();
+            match constant_time_eq(token.as_bytes(), secret.as_bytes()) {
+                true => Ok(()),
+                false => Err(ApiError::Unauthorized),
+            }
+        }
+    }
+}
+
+/// Verify the hex encoded HMAC-SHA256 signature of the body.
+fn verify_hmac(secret: &str, body: &[u8], signature: Option<&str>) -> Result<(), ApiError> {
+    let signature = signature.ok_or(ApiError::Unauthorized)?;
+    let signature = hex::decode(signature).map_err(|_| ApiError::Unauthorized)?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|_| ApiError::Unauthorized)?;
+    mac.update(body);
+    mac.verify_slice(&signature).map_err(|_| ApiError::Unauthorized)
+}
+
+#[inline]
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[inline]
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Normalize the repository address for comparing, e.g. all of
+/// `https://github.com/amphitheatre-app/amp-example-go.git`,
+/// `git@github.com:amphitheatre-app/amp-example-go.git` are
+/// normalized to `github.com/amphitheatre-app/amp-example-go`.
+fn normalize(address: &str) -> String {
+    let mut address = address.trim().to_lowercase();
+
+    for scheme in ["https://", "http://", "ssh://", "git://"] {
+        if let Some(rest) = address.strip_prefix(scheme) {
+            address = rest.to_string();
+            break;
+        }
+    }
+    if let Some(rest) = address.strip_prefix("git@") {
+        address = rest.replacen(':', "/", 1);
+    }
+
+    address.trim_end_matches('/').trim_end_matches(".git").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "It's a Secret to Everybody";
+
+    const GITHUB_PUSH: &[u8] = include_bytes!("../../tests/fixtures/webhooks/github_push.json");
+    const GITLAB_PUSH: &[u8] = include_bytes!("../../tests/fixtures/webhooks/gitlab_push.json");
+    const GITEA_PUSH: &[u8] = include_bytes!("../../tests/fixtures/webhooks/gitea_push.json");
+
+    fn sign(body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn github(event: &str, body: &[u8]) -> HeaderMap {
+        headers(&[
+            ("X-GitHub-Event", event.to_string()),
+            ("X-Hub-Signature-256", format!("sha256={}", sign(body))),
+        ])
+    }
+
+    fn source(repo: &str, branch: Option<&str>, tag: Option<&str>) -> Source {
+        serde_json::from_value(json!({ "repo": repo, "branch": branch, "tag": tag })).unwrap()
+    }
+
+    #[test]
+    fn test_verify_github_signature() {
+        let headers = github("push", GITHUB_PUSH);
+        assert!(verify(Provider::Github, &headers, GITHUB_PUSH, SECRET).is_ok());
+    }
+
+    #[test]
+    fn test_verify_github_invalid_signature() {
+        let headers = github("push", b"{}");
+        assert!(matches!(
+            verify(Provider::Github, &headers, GITHUB_PUSH, SECRET),
+            Err(ApiError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_verify_github_missing_signature() {
+        let headers = headers(&[("X-GitHub-Event", "push".to_string())]);
+        assert!(matches!(
+            verify(Provider::Github, &headers, GITHUB_PUSH, SECRET),
+            Err(ApiError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_verify_gitea_signature() {
+        let valid = headers(&[("X-Gitea-Signature", sign(GITEA_PUSH))]);
+        assert!(verify(Provider::Gitea, &valid, GITEA_PUSH, SECRET).is_ok());
+
+        let invalid = headers(&[("X-Gitea-Signature", "not-a-signature".to_string())]);
+        assert!(verify(Provider::Gitea, &invalid, GITEA_PUSH, SECRET).is_err());
+
+        assert!(verify(Provider::Gitea, &HeaderMap::new(), GITEA_PUSH, SECRET).is_err());
+    }
+
+    #[test]
+    fn test_verify_gitlab_token() {
+        let valid = headers(&[("X-Gitlab-Token", SECRET.to_string())]);
+        assert!(verify(Provider::Gitlab, &valid, GITLAB_PUSH, SECRET).is_ok());
+
+        let invalid = headers(&[("X-Gitlab-Token", "It's a Secret to Nobody".to_string())]);
+        assert!(verify(Provider::Gitlab, &invalid, GITLAB_PUSH, SECRET).is_err());
+
+        assert!(verify(Provider::Gitlab, &HeaderMap::new(), GITLAB_PUSH, SECRET).is_err());
+    }
+
+    #[test]
+    fn test_parse_github_push() {
+        let headers = github("push", GITHUB_PUSH);
+        let event = PushEvent::parse(Provider::Github, &headers, GITHUB_PUSH, SECRET).unwrap();
+
+        assert_eq!(
+            event,
+            Some(PushEvent {
+                repos: vec![
+                    "https://github.com/amphitheatre-app/amp-example-go.git".into(),
+                    "https://github.com/amphitheatre-app/amp-example-go".into(),
+                    "git@github.com:amphitheatre-app/amp-example-go.git".into(),
+                ],
+                branch: "master".into(),
+                rev: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_gitlab_push() {
+        let headers = headers(&[
+            ("X-Gitlab-Event", "Push Hook".to_string()),
+            ("X-Gitlab-Token", SECRET.to_string()),
+        ]);
+        let event = PushEvent::parse(Provider::Gitlab, &headers, GITLAB_PUSH, SECRET)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(event.branch, "master");
+        assert_eq!(event.rev, "da1560886d4f094c3e6c9ef40349f7d38b5d27d7");
+        assert!(event.matches(&source(
+            "https://gitlab.com/amphitheatre-app/amp-example-go",
+            Some("master"),
+            None
+        )));
+    }
+
+    #[test]
+    fn test_parse_gitea_push() {
+        let headers = headers(&[
+            ("X-Gitea-Event", "push".to_string()),
+            ("X-Gitea-Signature", sign(GITEA_PUSH)),
+        ]);
+        let event = PushEvent::parse(Provider::Gitea, &headers, GITEA_PUSH, SECRET)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(event.branch, "develop");
+        assert_eq!(event.rev, "bffeb74224043ba2feb48d137756c8a9331c449a");
+        assert!(event.matches(&source(
+            "git@gitea.com:amphitheatre-app/amp-example-go.git",
+            Some("develop"),
+            None
+        )));
+    }
+
+    #[test]
+    fn test_parse_ignores_other_events() {
+        let headers = github("ping", GITHUB_PUSH);
+        let event = PushEvent::parse(Provider::Github, &headers, GITHUB_PUSH, SECRET).unwrap();
+        assert_eq!(event, None);
+    }
+
+    #[test]
+    fn test_parse_ignores_tags_and_deleted_branches() {
+        let tag = br#"{"ref": "refs/tags/v1.0.0", "after": "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c"}"#;
+        let event = PushEvent::parse(Provider::Github, &github("push", tag), tag, SECRET).unwrap();
+        assert_eq!(event, None);
+
+        let deleted = br#"{"ref": "refs/heads/master", "after": "0000000000000000000000000000000000000000"}"#;
+        let event = PushEvent::parse(Provider::Github, &github("push", deleted), deleted, SECRET).unwrap();
+        assert_eq!(event, None);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_payload() {
+        let body = b"not a json";
+        assert!(matches!(
+            PushEvent::parse(Provider::Github, &github("push", body), body, SECRET),
+            Err(ApiError::BadRequest)
+        ));
+    }
+
+    #[test]
+    fn test_matches_source() {
+        let event = PushEvent {
+            repos: vec!["https://github.com/amphitheatre-app/amp-example-go.git".into()],
+            branch: "master".into(),
+            rev: "0d1a26e67d8f5eaf1f6ba5c57fc3c7d91ac0fd1c".into(),
+        };
+
+        let repo = "git@github.com:Amphitheatre-App/amp-example-go.git";
+        assert!(event.matches(&source(repo, Some("master"), None)));
+        assert!(event.matches(&source(
+            "https://github.com/amphitheatre-app/amp-example-go/",
+            Some("master"),
+            None
+        )));
+
+        // Another branch, a pinned tag or another repository are not tracking the push.
+        assert!(!event.matches(&source(repo, Some("develop"), None)));
+        assert!(!event.matches(&source(repo, Some("master"), Some("v1.0.0"))));
+        assert!(!event.matches(&source(repo, None, None)));
+        assert!(!event.matches(&source(
+            "https://github.com/amphitheatre-app/amp-example-rust",
+            Some("master"),
+            None
+        )));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let expected = "github.com/amphitheatre-app/amp-example-go";
+        assert_eq!(
+            normalize("https://github.com/amphitheatre-app/amp-example-go.git"),
+            expected
+        );
+        assert_eq!(
+            normalize("git@github.com:amphitheatre-app/amp-example-go.git"),
+            expected
+        );
+        assert_eq!(normalize("ssh://github.com/amphitheatre-app/amp-example-go"), expected);
+        assert_eq!(
+            normalize(" HTTPS://GitHub.com/amphitheatre-app/amp-example-go/ "),
+            expected
+        );
+    }
+}
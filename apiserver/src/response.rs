@@ -79,6 +79,10 @@ pub enum ApiError {
     NotFound,
     #[error("Resolve Error")]
     ResolveError,
+    #[error("Bad Request")]
+    BadRequest,
+    #[error("Unauthorized")]
+    Unauthorized,
 }
 
 impl IntoResponse for ApiError {
@@ -89,6 +93,8 @@ impl IntoResponse for ApiError {
             Self::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             Self::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
             Self::ResolveError => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
+            Self::BadRequest => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
         };
         (status, Json(json!({ "message": message }))).into_response()
     }
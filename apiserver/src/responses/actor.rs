@@ -12,6 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use amp_common::schema::Actor;
+use amp_resources::annotation;
+use amp_resources::ingress::URLS_ANNOTATION_KEY;
+use k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler;
+use kube::ResourceExt;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -23,4 +28,43 @@ pub struct ActorResponse {
     pub title: String,
     /// The description of the actor.
     pub description: String,
+    /// The public URLs of the exposed ports of the actor.
+    pub urls: Vec<String>,
+}
+
+impl From<Actor> for ActorResponse {
+    fn from(actor: Actor) -> Self {
+        Self {
+            id: actor.uid().unwrap_or_default(),
+            title: actor.name_any(),
+            description: String::new(),
+            urls: annotation(&actor, URLS_ANNOTATION_KEY).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AutoscalingResponse {
+    /// The lower limit of the number of pods.
+    pub min_replicas: i32,
+    /// The upper limit of the number of pods.
+    pub max_replicas: i32,
+    /// The current number of pods.
+    pub current_replicas: i32,
+    /// The desired number of pods, as last calculated by the autoscaler.
+    pub desired_replicas: i32,
+}
+
+impl From<HorizontalPodAutoscaler> for AutoscalingResponse {
+    fn from(autoscaler: HorizontalPodAutoscaler) -> Self {
+        let spec = autoscaler.spec.unwrap_or_default();
+        let status = autoscaler.status.unwrap_or_default();
+
+        Self {
+            min_replicas: spec.min_replicas.unwrap_or(1),
+            max_replicas: spec.max_replicas,
+            current_replicas: status.current_replicas.unwrap_or_default(),
+            desired_replicas: status.desired_replicas,
+        }
+    }
 }
@@ -14,7 +14,7 @@
 
 use std::sync::Arc;
 
-use axum::routing::{delete, get, patch, post};
+use axum::routing::{delete, get, patch, post, put};
 use axum::Router;
 
 use crate::context::Context;
@@ -27,6 +27,8 @@ pub fn build() -> Router<Arc<Context>> {
         .route("/v1/actors/:id/logs", get(handlers::actor::logs))
         .route("/v1/actors/:id/info", get(handlers::actor::info))
         .route("/v1/actors/:id/stats", get(handlers::actor::stats))
+        .route("/v1/actors/:id/actions/cancel", post(handlers::actor::cancel))
+        .route("/v1/actors/:id/actions/scale", post(handlers::actor::scale))
         //
         // playbooks
         .route("/v1/playbooks", get(handlers::playbook::list))
@@ -37,6 +39,11 @@ pub fn build() -> Router<Arc<Context>> {
         //
         .route("/v1/playbooks/:id/actions/start", post(handlers::playbook::start))
         .route("/v1/playbooks/:id/actions/stop", post(handlers::playbook::stop))
+        .route("/v1/playbooks/:id/actions/update", post(handlers::playbook::refresh))
         .route("/v1/playbooks/:id/events", get(handlers::playbook::events))
         .route("/v1/playbooks/:id/actors", get(handlers::actor::list))
+        .route("/v1/playbooks/:id/secrets/:name", put(handlers::playbook::secret))
+        //
+        // webhooks
+        .route("/v1/webhooks/:provider", post(handlers::webhook::receive))
 }
@@ -14,20 +14,174 @@
 
 use std::sync::Arc;
 
+use amp_common::schema::Actor;
+use amp_resources::pod::REPLICAS_ANNOTATION_KEY;
+use amp_resources::{actor, builder, configuration, horizontal_pod_autoscaler, image, job, playbook, pod, settings};
+use futures::stream::{self, BoxStream};
+use futures::{future, StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::LogParams;
+use kube::{Api, ResourceExt};
+use tracing::error;
 use uuid::Uuid;
 
 use crate::context::Context;
-use crate::responses::actor::ActorResponse;
+use crate::response::ApiError;
+use crate::responses::actor::{ActorResponse, AutoscalingResponse};
 use crate::services::Result;
 
 pub struct ActorService;
 
 impl ActorService {
-    pub async fn get(_ctx: Arc<Context>, _id: Uuid) -> Result<ActorResponse> {
-        unimplemented!()
+    pub async fn get(ctx: Arc<Context>, id: Uuid) -> Result<ActorResponse> {
+        let resource = find(&ctx, id).await?;
+
+        Ok(resource.into())
+    }
+
+    pub async fn list(ctx: Arc<Context>, pid: Uuid) -> Result<Vec<ActorResponse>> {
+        let playbook = playbook::get(&ctx.k8s, &pid.to_string()).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+        let resources = actor::list(&ctx.k8s, &playbook).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        Ok(resources.into_iter().map(|actor| actor.into()).collect())
     }
 
-    pub async fn list(_ctx: Arc<Context>, _pid: Uuid) -> Result<Vec<ActorResponse>> {
-        unimplemented!()
+    /// The logs of actor, they're the logs of its latest build while it's building,
+    /// else, the logs of its latest pod are followed.
+    pub async fn logs(ctx: Arc<Context>, id: Uuid) -> Result<BoxStream<'static, std::result::Result<String, String>>> {
+        let resource = find(&ctx, id).await?;
+
+        if resource.status.as_ref().map_or(false, |status| status.building()) {
+            let configuration = configuration::load(&ctx.k8s, &ctx.config.namespace)
+                .await
+                .map_err(|err| {
+                    error!("{:?}", err);
+                    ApiError::KubernetesError
+                })?;
+            let builder = builder::new(&resource, &configuration).map_err(|err| {
+                error!("{:?}", err);
+                ApiError::InternalServerError
+            })?;
+            let content = builder.logs(&ctx.k8s, &resource).await.map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+            return Ok(stream::once(future::ready(Ok(content))).boxed());
+        }
+
+        let pod = pod::latest(&ctx.k8s, &resource)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?
+            .ok_or(ApiError::NotFound)?;
+
+        let api: Api<Pod> = Api::namespaced(ctx.k8s.clone(), &pod.namespace().unwrap_or_default());
+        let params = LogParams {
+            container: Some(resource.name_any()),
+            follow: true,
+            ..Default::default()
+        };
+        let stream = api
+            .log_stream(&pod.name_any(), &params)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?
+            .map_ok(|line| String::from_utf8_lossy(&line).into_owned())
+            .map_err(|err| err.to_string());
+
+        Ok(stream.boxed())
     }
+
+    /// Cancel the in-flight build of actor, and mark it as failed.
+    pub async fn cancel(ctx: Arc<Context>, id: Uuid) -> Result<()> {
+        let resource = find(&ctx, id).await?;
+
+        if !resource.status.as_ref().map_or(false, |status| status.building()) {
+            return Err(ApiError::BadRequest);
+        }
+
+        // Mark it as failed first, so that the controller doesn't start the build
+        // again when it sees the Job or Image is gone.
+        let condition = actor::failed("BuildCancelled", "The build was cancelled");
+        actor::patch_status(&ctx.k8s, &resource, condition)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+        job::delete(&ctx.k8s, &resource).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+        image::delete(&ctx.k8s, &resource).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        Ok(())
+    }
+
+    /// The state of the HorizontalPodAutoscaler of actor, if it's autoscaled.
+    pub async fn autoscaling(ctx: Arc<Context>, id: Uuid) -> Result<Option<AutoscalingResponse>> {
+        let resource = find(&ctx, id).await?;
+
+        let autoscaler = horizontal_pod_autoscaler::get(&ctx.k8s, &resource)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+        Ok(autoscaler.map(|autoscaler| autoscaler.into()))
+    }
+
+    /// Scale the actor to the number of pods, it's kept until the actor is scaled again.
+    pub async fn scale(ctx: Arc<Context>, id: Uuid, replicas: i32) -> Result<()> {
+        if replicas < 0 {
+            return Err(ApiError::BadRequest);
+        }
+
+        let resource = find(&ctx, id).await?;
+
+        // The number of pods is managed by the HorizontalPodAutoscaler.
+        let settings = settings::of(&resource).map_err(|err| {
+            error!("{:?}", err);
+            ApiError::InternalServerError
+        })?;
+        if settings.deploy.autoscaling.is_some() {
+            return Err(ApiError::BadRequest);
+        }
+
+        actor::annotate(&ctx.k8s, &resource, REPLICAS_ANNOTATION_KEY, &replicas.to_string())
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+        Ok(())
+    }
+}
+
+/// Find the actor by its id.
+async fn find(ctx: &Context, id: Uuid) -> Result<Actor> {
+    actor::find(&ctx.k8s, &id.to_string())
+        .await
+        .map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?
+        .ok_or(ApiError::NotFound)
 }
@@ -14,6 +14,7 @@
 
 pub mod actor;
 pub mod playbook;
+pub mod webhook;
 
 use crate::response::ApiError;
 pub type Result<T, E = ApiError> = std::result::Result<T, E>;
@@ -12,12 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
-use amp_common::schema::{Playbook as PlaybookResource, PlaybookSpec};
-use amp_resources::playbook;
+use amp_common::schema::{ActorSpec, Playbook as PlaybookResource, PlaybookSpec, PlaybookState};
+use amp_resolver::lock::Lock;
+use amp_resources::playbook::{
+    self, CACHE_REPO_ANNOTATION_KEY, LOCKED_ANNOTATION_KEY, LOCK_ANNOTATION_KEY, OVERRIDES_ANNOTATION_KEY,
+    VARIABLES_ANNOTATION_KEY,
+};
+use amp_resources::{annotation, secret};
 use chrono::Utc;
+use k8s_openapi::ByteString;
 use kube::ResourceExt;
+use serde_json::to_string;
 use tracing::error;
 use uuid::Uuid;
 
@@ -56,6 +64,62 @@ impl PlaybookService {
         unimplemented!()
     }
 
+    /// Re-resolve the actors tracking a branch or tag to their latest
+    /// revisions, and update the lock of playbook.
+    pub async fn refresh(ctx: Arc<Context>, id: Uuid) -> Result<()> {
+        let resource = playbook::get(&ctx.k8s, &id.to_string()).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        Self::reresolve(&ctx, &resource, |actor| {
+            actor.source.tag.is_some() || actor.source.branch.is_some()
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Clear the resolved revisions of the matching actors, the controller
+    /// will resolve them again, returns the names of these actors.
+    pub async fn reresolve<F>(ctx: &Arc<Context>, resource: &PlaybookResource, predicate: F) -> Result<Vec<String>>
+    where
+        F: Fn(&ActorSpec) -> bool,
+    {
+        let mut lock: Lock = annotation(resource, LOCK_ANNOTATION_KEY).map_err(|err| {
+            error!("{:?}", err);
+            ApiError::InternalServerError
+        })?;
+
+        let mut actors = vec![];
+        for actor in resource.spec.actors.iter().flatten().filter(|actor| predicate(actor)) {
+            let mut actor = actor.clone();
+            actor.source.rev = None;
+            lock.actors.remove(&actor.name);
+            actors.push(actor);
+        }
+
+        let names: Vec<String> = actors.iter().map(|actor| actor.name.clone()).collect();
+        if actors.is_empty() {
+            return Ok(names);
+        }
+
+        playbook::add_annotated(&ctx.k8s, resource, actors, LOCK_ANNOTATION_KEY, &lock)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+        playbook::patch_status(&ctx.k8s, resource, PlaybookState::resolving())
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+        Ok(names)
+    }
+
     pub async fn delete(ctx: Arc<Context>, id: Uuid) -> Result<()> {
         playbook::delete(&ctx.k8s, &id.to_string()).await.map_err(|err| {
             error!("{:?}", err);
@@ -65,9 +129,39 @@ impl PlaybookService {
         Ok(())
     }
 
+    /// Set the runtime secret in the namespace of playbook, it's never
+    /// overwritten by the platform one with the same name.
+    pub async fn secret(ctx: Arc<Context>, id: Uuid, name: &str, data: HashMap<String, String>) -> Result<()> {
+        if !secret::valid_name(name) {
+            return Err(ApiError::BadRequest);
+        }
+
+        let playbook = playbook::get(&ctx.k8s, &id.to_string()).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        let data = data
+            .into_iter()
+            .map(|(key, value)| (key, ByteString(value.into_bytes())))
+            .collect();
+        secret::create(
+            &ctx.k8s,
+            &playbook.spec.namespace,
+            secret::runtime(name, "playbook", data),
+        )
+        .await
+        .map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        Ok(())
+    }
+
     pub async fn create(ctx: Arc<Context>, req: &CreatePlaybookRequest) -> Result<PlaybookResponse> {
         let uuid = Uuid::new_v4();
-        let resource = PlaybookResource::new(
+        let mut resource = PlaybookResource::new(
             &uuid.to_string(),
             PlaybookSpec {
                 title: req.title.to_string(),
@@ -78,6 +172,23 @@ impl PlaybookService {
             },
         );
 
+        let mut annotations = BTreeMap::new();
+        if let Some(variables) = &req.variables {
+            let value = to_string(variables).map_err(|_| ApiError::InternalServerError)?;
+            annotations.insert(VARIABLES_ANNOTATION_KEY.to_string(), value);
+        }
+        if let Some(overrides) = &req.overrides {
+            let value = to_string(overrides).map_err(|_| ApiError::InternalServerError)?;
+            annotations.insert(OVERRIDES_ANNOTATION_KEY.to_string(), value);
+        }
+        if let Some(locked) = req.locked {
+            annotations.insert(LOCKED_ANNOTATION_KEY.to_string(), locked.to_string());
+        }
+        if let Some(repo) = &req.cache_repo {
+            annotations.insert(CACHE_REPO_ANNOTATION_KEY.to_string(), repo.clone());
+        }
+        resource.annotations_mut().extend(annotations);
+
         let playbook = playbook::create(&ctx.k8s, &resource).await.map_err(|err| {
             error!("{:?}", err);
             ApiError::KubernetesError
@@ -0,0 +1,82 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use amp_resources::{configuration, playbook};
+use axum::http::HeaderMap;
+use tracing::{error, info};
+
+use crate::context::Context;
+use crate::requests::webhook::{Provider, PushEvent};
+use crate::response::ApiError;
+use crate::services::playbook::PlaybookService;
+use crate::services::Result;
+
+pub struct WebhookService;
+
+impl WebhookService {
+    /// Receive the push event, and re-resolve the actors tracking the pushed
+    /// branch, so that they are rebuilt. Returns the names of these actors.
+    pub async fn receive(
+        ctx: Arc<Context>,
+        provider: Provider,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Vec<String>> {
+        let configuration = configuration::load(&ctx.k8s, &ctx.config.namespace)
+            .await
+            .map_err(|err| {
+                error!("{:?}", err);
+                ApiError::KubernetesError
+            })?;
+
+        let secret = match provider {
+            Provider::Github => configuration.webhooks.github,
+            Provider::Gitlab => configuration.webhooks.gitlab,
+            Provider::Gitea => configuration.webhooks.gitea,
+        };
+        let secret = secret.ok_or_else(|| {
+            error!("The webhook secret of {:?} is not configured", provider);
+            ApiError::Unauthorized
+        })?;
+
+        let event = match PushEvent::parse(provider, headers, body, &secret)? {
+            Some(event) => event,
+            None => return Ok(vec![]),
+        };
+        info!("Received push event: {:?}", event);
+
+        let playbooks = playbook::list(&ctx.k8s).await.map_err(|err| {
+            error!("{:?}", err);
+            ApiError::KubernetesError
+        })?;
+
+        let mut actors = vec![];
+        for resource in playbooks.iter() {
+            // Leave the locked playbook until it's updated explicitly.
+            if playbook::locked(resource) {
+                continue;
+            }
+
+            let names = PlaybookService::reresolve(&ctx, resource, |actor| {
+                event.matches(&actor.source) && actor.source.rev.as_deref() != Some(event.rev.as_str())
+            })
+            .await?;
+            actors.extend(names);
+        }
+
+        Ok(actors)
+    }
+}
@@ -24,6 +24,8 @@ use crate::{handlers, requests, responses};
         handlers::actor::logs,
         handlers::actor::info,
         handlers::actor::stats,
+        handlers::actor::cancel,
+        handlers::actor::scale,
         //
         handlers::playbook::list,
         handlers::playbook::create,
@@ -32,21 +34,29 @@ use crate::{handlers, requests, responses};
         handlers::playbook::delete,
         handlers::playbook::start,
         handlers::playbook::stop,
+        handlers::playbook::refresh,
         handlers::playbook::events,
         handlers::actor::list,
+        handlers::playbook::secret,
+        //
+        handlers::webhook::receive,
     ),
     components(
         schemas(
             requests::playbook::CreatePlaybookRequest,
             requests::playbook::UpdatePlaybookRequest,
+            requests::playbook::SetSecretRequest,
+            requests::actor::ScaleActorRequest,
             //
             responses::actor::ActorResponse,
+            responses::actor::AutoscalingResponse,
             responses::playbook::PlaybookResponse,
         )
     ),
     tags(
         (name = "Actors", description = "The Actors Service Handlers"),
         (name = "Playbooks", description = "The Playbooks Service Handlers"),
+        (name = "Webhooks", description = "The Webhooks Service Handlers"),
     ),
 )]
 struct ApiDoc;
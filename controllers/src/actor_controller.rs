@@ -12,19 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
+use amp_common::config::CredentialConfiguration;
 use amp_common::docker::{self, registry, DockerConfig};
 use amp_common::schema::{Actor, ActorState};
+use amp_resolver as resolver;
+use amp_resources::builder::{Builder, BUILD_SECRETS_NAME, PLATFORMS_ANNOTATION_KEY};
+use amp_resources::digest::{self, DIGEST_ANNOTATION_KEY};
 use amp_resources::event::trace;
-use amp_resources::{actor, deployment, image, job, service};
+use amp_resources::ingress::{self, URLS_ANNOTATION_KEY};
+use amp_resources::settings::Workload;
+use amp_resources::{
+    actor, annotation, builder, config_map, deployment, horizontal_pod_autoscaler, image, persistent_volume_claim,
+    secret, service, settings, stateful_set,
+};
 use futures::{future, StreamExt};
-use k8s_openapi::api::core::v1::Namespace;
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{Namespace, Secret, Service};
+use k8s_openapi::api::networking::v1::Ingress;
+use k8s_openapi::chrono::Utc;
 use kube::api::ListParams;
+use kube::core::DynamicObject;
 use kube::runtime::controller::Action;
 use kube::runtime::events::Recorder;
 use kube::runtime::finalizer::{finalizer, Event as FinalizerEvent};
+use kube::runtime::reflector::ObjectRef;
 use kube::runtime::Controller;
 use kube::{Api, Resource, ResourceExt};
 
@@ -41,7 +57,42 @@ pub async fn new(ctx: &Arc<Context>) {
         std::process::exit(1);
     }
 
-    Controller::new(api, ListParams::default())
+    // Watch the resources owned by actors, so that their status transitions
+    // (e.g. build succeeded, rollout complete) trigger the reconciler immediately.
+    let params = ListParams::default().labels("app.kubernetes.io/managed-by=Amphitheatre");
+    let resource = image::api_resource();
+
+    let mut controller = Controller::new(api, ListParams::default());
+    let store = controller.store();
+
+    // The runtime secrets are referenced by the pods of actors, reconcile the
+    // actors referencing them when they're changed, so that the pods are rolled.
+    let secrets = ListParams::default().labels(secret::SCOPE_LABEL_KEY);
+    controller = controller
+        .owns(Api::<Job>::all(ctx.k8s.clone()), params.clone())
+        .owns(Api::<Deployment>::all(ctx.k8s.clone()), params.clone())
+        .owns(Api::<StatefulSet>::all(ctx.k8s.clone()), params.clone())
+        .owns(Api::<Service>::all(ctx.k8s.clone()), params.clone())
+        .owns(Api::<Ingress>::all(ctx.k8s.clone()), params.clone())
+        .watches(Api::<Secret>::all(ctx.k8s.clone()), secrets, move |secret| {
+            store
+                .state()
+                .into_iter()
+                .filter(|actor| actor.namespace() == secret.namespace())
+                .filter(|actor| secret::references(actor, &secret.name_any()))
+                .map(|actor| ObjectRef::from_obj(actor.as_ref()))
+                .collect::<Vec<_>>()
+        });
+
+    // The Images of kpack are watched only if kpack is installed, otherwise
+    // the watch fails and stops the controller.
+    let images = Api::<DynamicObject>::all_with(ctx.k8s.clone(), &resource);
+    match images.list(&ListParams::default().limit(1)).await {
+        Ok(_) => controller = controller.owns_with(images, resource, params),
+        Err(e) => tracing::warn!("The kpack Images are not watched, is kpack installed? {e:?}"),
+    }
+
+    controller
         .run(reconcile, error_policy, ctx.clone())
         .for_each(|_| future::ready(()))
         .await
@@ -100,11 +151,14 @@ async fn init(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<
 }
 
 async fn build(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<Action> {
+    let settings = settings::of(actor).map_err(Error::ResourceError)?;
+    let image = settings.image(&actor.spec);
+
     // Return if the image already exists
     let configuration = ctx.configuration.read().await;
     let config = DockerConfig::from(&configuration.registries);
 
-    let credential = docker::get_credential(&config, &actor.spec.image);
+    let credential = docker::get_credential(&config, &image);
     let credential = match credential {
         Ok(credential) => Some(credential),
         Err(err) => {
@@ -113,45 +167,106 @@ async fn build(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result
         }
     };
 
-    if registry::exists(&actor.spec.docker_tag(), credential)
+    if registry::exists(&image, credential)
         .await
         .map_err(Error::DockerRegistryExistsFailed)?
     {
         tracing::info!("The images already exists, Running");
-        let condition = ActorState::running(true, "AutoRun", None);
-        actor::patch_status(&ctx.k8s, actor, condition)
-            .await
-            .map_err(Error::ResourceError)?;
+        return start(actor, ctx, &configuration, &image).await;
+    }
 
-        return Ok(Action::await_change());
+    // The prebuilt image has no source to build, it must be pullable.
+    if settings.prebuilt() {
+        return fail(actor, ctx, recorder, "ImageNotFound").await;
     }
 
-    // Prefer to use Kaniko to build images with Dockerfile,
-    // else, build the image with Cloud Native Buildpacks
-    if actor.spec.has_dockerfile() {
-        tracing::debug!("Found dockerfile, build it with kaniko");
-        build_with_kaniko(actor, ctx, recorder).await?;
+    let (builder, secrets) = {
+        let platform = ctx.platform.read().await;
+        let builder = builder::new(actor, &platform).map_err(Error::ResourceError)?;
+        (builder, builder::keys(actor, &platform).map_err(Error::ResourceError)?)
+    };
+
+    // Reject the build the backend can't handle, before any resource is created.
+    if let Some(reason) = builder.unsupported(actor).map_err(Error::ResourceError)? {
+        return fail(actor, ctx, recorder, &reason).await;
+    }
 
-        // Check If the build Job has not completed, requeue the reconciler.
-        if !job::completed(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
-            return Ok(Action::requeue(Duration::from_secs(60)));
+    // Materialize the build-time secrets for the build, so that their
+    // values are never inlined into the spec of build resources.
+    if !secrets.is_empty() {
+        let source = secret::read(&ctx.k8s, &ctx.config.namespace, BUILD_SECRETS_NAME)
+            .await
+            .map_err(Error::ResourceError)?;
+        let missing = builder::materialize(&ctx.k8s, actor, source, &secrets)
+            .await
+            .map_err(Error::ResourceError)?;
+        if !missing.is_empty() {
+            tracing::error!("The build secrets {:?} of {} are not found", missing, actor.name_any());
+            return fail(actor, ctx, recorder, "BuildSecretNotFound").await;
         }
-    } else {
-        tracing::debug!("Build the image with Cloud Native Buildpacks");
-        build_with_kpack(actor, ctx, recorder).await?;
+    }
 
-        // Check If the build Image has not completed, requeue the reconciler.
-        if !image::completed(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
-            return Ok(Action::requeue(Duration::from_secs(60)));
+    let name = actor.spec.build_name();
+    match builder.exists(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+        true => {
+            // Build resource already exists, update it if there are new changes
+            trace(recorder, format!("Try to refresh an existing build {}", name))
+                .await
+                .map_err(Error::ResourceError)?;
+            builder.update(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
+        }
+        false => {
+            // Create a new build resource
+            trace(recorder, format!("Create new build: {}", name))
+                .await
+                .map_err(Error::ResourceError)?;
+            builder.create(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
         }
     }
 
+    // Check If the build has failed or timed out.
+    if let Some(reason) = builder.failed(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+        return fail(actor, ctx, recorder, &reason).await;
+    }
+
+    // Check If the build has not completed, wait for the status changes of the
+    // build resource, and requeue the reconciler to check if it has timed out.
+    if !builder.completed(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+        report(actor, ctx, builder.as_ref()).await?;
+        let started = builder.started(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
+        return Ok(Action::requeue(builder::recheck(
+            started,
+            settings.build.timeout(),
+            Utc::now(),
+        )));
+    }
+
     // Once the image is built, it is deployed to the cluster with the
     // appropriate resource type (e.g. Deployment or StatefulSet).
     let message = "The images builded, Running";
     trace(recorder, message).await.map_err(Error::ResourceError)?;
 
-    let condition = ActorState::running(true, "AutoRun", None);
+    start(actor, ctx, &configuration, &image).await
+}
+
+/// Pin the image by its content digest, and mark the actor as running,
+/// so that the rollouts are reproducible even if the tag is re-pushed.
+async fn start(
+    actor: &Actor,
+    ctx: &Arc<Context>,
+    configuration: &CredentialConfiguration,
+    image: &str,
+) -> Result<Action> {
+    let reference = digest::resolve(configuration, image)
+        .await
+        .map_err(Error::ResourceError)?;
+    actor::annotate(&ctx.k8s, actor, DIGEST_ANNOTATION_KEY, &reference)
+        .await
+        .map_err(Error::ResourceError)?;
+
+    // It's ready once the rollout of workload has completed, see `run()`.
+    let mut condition = ActorState::running(true, "Deploying", None);
+    condition.message = format!("Running image {}", reference);
     actor::patch_status(&ctx.k8s, actor, condition)
         .await
         .map_err(Error::ResourceError)?;
@@ -159,46 +274,85 @@ async fn build(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result
     Ok(Action::await_change())
 }
 
-async fn build_with_kaniko(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<()> {
-    match job::exists(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
-        true => {
-            // Build job already exists, update it if there are new changes
-            let message = format!("Try to refresh an existing build Job {}", actor.spec.build_name());
-            trace(recorder, message).await.map_err(Error::ResourceError)?;
-
-            job::update(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
-        }
-        false => {
-            // Create a new build job
-            let message = format!("Create new build Job: {}", actor.spec.build_name());
-            trace(recorder, message).await.map_err(Error::ResourceError)?;
+/// Record the states of the build for each target platform on the actor.
+async fn report(actor: &Actor, ctx: &Arc<Context>, builder: &dyn Builder) -> Result<()> {
+    let platforms = builder.platforms(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
+    if platforms.is_empty() {
+        return Ok(());
+    }
 
-            job::create(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
-        }
+    let value = serde_json::to_string(&platforms).map_err(Error::SerializationError)?;
+    if actor.annotations().get(PLATFORMS_ANNOTATION_KEY) != Some(&value) {
+        actor::annotate(&ctx.k8s, actor, PLATFORMS_ANNOTATION_KEY, &value)
+            .await
+            .map_err(Error::ResourceError)?;
     }
 
     Ok(())
 }
 
-async fn build_with_kpack(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<()> {
-    match image::exists(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
-        true => {
-            // Image already exists, update it if there are new changes
-            let message = format!("Try to refresh an existing Image {}", actor.spec.build_name());
-            trace(recorder, message).await.map_err(Error::ResourceError)?;
+/// Materialize the configs declared by actor as ConfigMaps, the files are fetched
+/// at the revision of actor only if they're not applied yet. Returns true if the
+/// hashes of their contents are changed, and annotated on the actor.
+async fn configs(actor: &Actor, ctx: &Arc<Context>) -> Result<bool> {
+    let settings = settings::of(actor).map_err(Error::ResourceError)?;
 
-            image::update(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
-        }
-        false => {
-            // Create a new image
-            let message = format!("Create new image: {}", actor.spec.build_name());
-            trace(recorder, message).await.map_err(Error::ResourceError)?;
+    let mut hashes = BTreeMap::new();
+    for config in settings.deploy.configs.iter() {
+        let applied = config_map::applied(&ctx.k8s, actor, config)
+            .await
+            .map_err(Error::ResourceError)?;
+        let hash = match applied {
+            Some(hash) => hash,
+            None => {
+                let configuration = ctx.configuration.read().await.clone();
+                let data = resolver::files(&configuration, &actor.spec.source, config).map_err(Error::ResolveError)?;
+                config_map::apply(&ctx.k8s, actor, config, data)
+                    .await
+                    .map_err(Error::ResourceError)?
+            }
+        };
+        hashes.insert(config.name.clone(), hash);
+    }
+    config_map::prune(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
 
-            image::create(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
-        }
+    annotate_hashes(actor, ctx, config_map::HASHES_ANNOTATION_KEY, &hashes).await
+}
+
+/// Annotate the actor with the hashes of contents if they're changed, they're
+/// annotated on its pods as well. Returns true if they're changed.
+async fn annotate_hashes(
+    actor: &Actor,
+    ctx: &Arc<Context>,
+    key: &str,
+    hashes: &BTreeMap<String, String>,
+) -> Result<bool> {
+    let value = serde_json::to_string(hashes).map_err(Error::SerializationError)?;
+    let unchanged = match actor.annotations().get(key) {
+        Some(current) => current == &value,
+        None => hashes.is_empty(),
+    };
+    if unchanged {
+        return Ok(false);
     }
 
-    Ok(())
+    actor::annotate(&ctx.k8s, actor, key, &value)
+        .await
+        .map_err(Error::ResourceError)?;
+
+    Ok(true)
+}
+
+/// Mark the actor as failed, it will not be reconciled until it's changed.
+async fn fail(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder, reason: &str) -> Result<Action> {
+    let message = format!("The build of Actor {} failed: {}", actor.name_any(), reason);
+    trace(recorder, &message).await.map_err(Error::ResourceError)?;
+
+    actor::patch_status(&ctx.k8s, actor, actor::failed(reason, message))
+        .await
+        .map_err(Error::ResourceError)?;
+
+    Ok(Action::await_change())
 }
 
 async fn run(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<Action> {
@@ -209,28 +363,79 @@ async fn run(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<A
     .await
     .map_err(Error::ResourceError)?;
 
-    match deployment::exists(&ctx.k8s, actor)
-        .await
-        .map_err(Error::ResourceError)?
-    {
-        true => {
-            // Deployment already exists, update it if there are new changes
-            let message = format!("Try to refresh an existing Deployment {}", actor.name_any());
-            trace(recorder, message).await.map_err(Error::ResourceError)?;
+    // The pods are rolled with the new contents of configs and secrets, once
+    // the actor is annotated with them, it's reconciled again.
+    let configs_changed = configs(actor, ctx).await?;
+    let secrets = secret::hashes(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
+    let secrets_changed = annotate_hashes(actor, ctx, secret::HASHES_ANNOTATION_KEY, &secrets).await?;
+    if configs_changed || secrets_changed {
+        return Ok(Action::await_change());
+    }
 
-            deployment::update(&ctx.k8s, actor)
+    // Run the actor with the workload declared in the manifest, and
+    // remove the other one if the kind of workload has been changed.
+    let ready = match settings::of(actor).map_err(Error::ResourceError)?.deploy.kind {
+        Workload::Deployment => {
+            stateful_set::delete(&ctx.k8s, actor)
                 .await
                 .map_err(Error::ResourceError)?;
-        }
-        false => {
-            // Create a new Deployment
-            trace(recorder, format!("Create new Deployment: {}", actor.name_any()))
+            persistent_volume_claim::apply(&ctx.k8s, actor)
                 .await
                 .map_err(Error::ResourceError)?;
-            deployment::create(&ctx.k8s, actor)
+            run_deployment(actor, ctx, recorder).await?;
+            deployment::ready(&ctx.k8s, actor).await.map_err(Error::ResourceError)?
+        }
+        Workload::StatefulSet => {
+            deployment::delete(&ctx.k8s, actor)
                 .await
                 .map_err(Error::ResourceError)?;
+            run_stateful_set(actor, ctx, recorder).await?;
+            stateful_set::ready(&ctx.k8s, actor)
+                .await
+                .map_err(Error::ResourceError)?
+        }
+    };
+    persistent_volume_claim::retain(&ctx.k8s, actor)
+        .await
+        .map_err(Error::ResourceError)?;
+
+    // Scale the workload automatically if the autoscaling is declared.
+    if settings::of(actor)
+        .map_err(Error::ResourceError)?
+        .deploy
+        .autoscaling
+        .is_some()
+    {
+        match horizontal_pod_autoscaler::exists(&ctx.k8s, actor)
+            .await
+            .map_err(Error::ResourceError)?
+        {
+            true => {
+                // HorizontalPodAutoscaler already exists, update it if there are new changes
+                let message = format!(
+                    "Try to refresh an existing HorizontalPodAutoscaler {}",
+                    actor.name_any()
+                );
+                trace(recorder, message).await.map_err(Error::ResourceError)?;
+
+                horizontal_pod_autoscaler::update(&ctx.k8s, actor)
+                    .await
+                    .map_err(Error::ResourceError)?;
+            }
+            false => {
+                // Create a new HorizontalPodAutoscaler
+                let message = format!("Create new HorizontalPodAutoscaler: {}", actor.name_any());
+                trace(recorder, message).await.map_err(Error::ResourceError)?;
+
+                horizontal_pod_autoscaler::create(&ctx.k8s, actor)
+                    .await
+                    .map_err(Error::ResourceError)?;
+            }
         }
+    } else {
+        horizontal_pod_autoscaler::delete(&ctx.k8s, actor)
+            .await
+            .map_err(Error::ResourceError)?;
     }
 
     if actor.spec.service_ports().is_some() {
@@ -252,9 +457,119 @@ async fn run(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<A
         }
     }
 
+    expose(actor, ctx).await?;
+
+    // The actor is deploying until the rollout of workload has completed,
+    // the status changes of workload trigger the reconciliation again.
+    let reason = if ready { "Ready" } else { "Deploying" };
+    if actor::running_reason(actor).as_deref() != Some(reason) {
+        trace(
+            recorder,
+            format!("The Actor {} is {}", actor.name_any(), reason.to_lowercase()),
+        )
+        .await
+        .map_err(Error::ResourceError)?;
+
+        let mut condition = ActorState::running(true, reason, None);
+        if let Some(reference) = digest::of(actor) {
+            condition.message = format!("Running image {}", reference);
+        }
+        actor::patch_status(&ctx.k8s, actor, condition)
+            .await
+            .map_err(Error::ResourceError)?;
+    }
+
     Ok(Action::await_change())
 }
 
+/// Expose the declared ports of actor with Ingress, and record their URLs on the actor,
+/// the Ingress is removed if no ports are exposed or the platform has no Ingress.
+async fn expose(actor: &Actor, ctx: &Arc<Context>) -> Result<()> {
+    let configuration = ctx.platform.read().await.ingress.clone();
+    let exposes = settings::of(actor).map_err(Error::ResourceError)?.deploy.exposes;
+
+    let urls = match configuration {
+        Some(configuration) if !exposes.is_empty() => {
+            match ingress::exists(&ctx.k8s, actor).await.map_err(Error::ResourceError)? {
+                true => ingress::update(&ctx.k8s, actor, &configuration).await,
+                false => ingress::create(&ctx.k8s, actor, &configuration).await,
+            }
+            .map_err(Error::ResourceError)?;
+            ingress::urls(actor, &configuration).map_err(Error::ResourceError)?
+        }
+        _ => {
+            ingress::delete(&ctx.k8s, actor).await.map_err(Error::ResourceError)?;
+            vec![]
+        }
+    };
+
+    let found: Vec<String> = annotation(actor, URLS_ANNOTATION_KEY).map_err(Error::ResourceError)?;
+    if found != urls {
+        let value = serde_json::to_string(&urls).map_err(Error::SerializationError)?;
+        actor::annotate(&ctx.k8s, actor, URLS_ANNOTATION_KEY, &value)
+            .await
+            .map_err(Error::ResourceError)?;
+    }
+
+    Ok(())
+}
+
+async fn run_deployment(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<()> {
+    match deployment::exists(&ctx.k8s, actor)
+        .await
+        .map_err(Error::ResourceError)?
+    {
+        true => {
+            // Deployment already exists, update it if there are new changes
+            let message = format!("Try to refresh an existing Deployment {}", actor.name_any());
+            trace(recorder, message).await.map_err(Error::ResourceError)?;
+
+            deployment::update(&ctx.k8s, actor)
+                .await
+                .map_err(Error::ResourceError)?;
+        }
+        false => {
+            // Create a new Deployment
+            trace(recorder, format!("Create new Deployment: {}", actor.name_any()))
+                .await
+                .map_err(Error::ResourceError)?;
+            deployment::create(&ctx.k8s, actor)
+                .await
+                .map_err(Error::ResourceError)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_stateful_set(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<()> {
+    match stateful_set::exists(&ctx.k8s, actor)
+        .await
+        .map_err(Error::ResourceError)?
+    {
+        true => {
+            // StatefulSet already exists, update it if there are new changes
+            let message = format!("Try to refresh an existing StatefulSet {}", actor.name_any());
+            trace(recorder, message).await.map_err(Error::ResourceError)?;
+
+            stateful_set::update(&ctx.k8s, actor)
+                .await
+                .map_err(Error::ResourceError)?;
+        }
+        false => {
+            // Create a new StatefulSet
+            trace(recorder, format!("Create new StatefulSet: {}", actor.name_any()))
+                .await
+                .map_err(Error::ResourceError)?;
+            stateful_set::create(&ctx.k8s, actor)
+                .await
+                .map_err(Error::ResourceError)?;
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn cleanup(actor: &Actor, ctx: &Arc<Context>, recorder: &Recorder) -> Result<Action> {
     let namespace = actor.namespace().unwrap();
     let api: Api<Namespace> = Api::all(ctx.k8s.clone());
@@ -37,4 +37,14 @@ pub struct Config {
     /// currently using, the default is `default`.
     #[clap(long, env = "AMP_SERVICE_ACCOUNT_NAME", default_value = "default")]
     pub service_account_name: String,
+
+    /// The interval in seconds to poll the branches tracked by actors
+    /// for new commits, the default is `300` and the minimum is `30`.
+    #[clap(long, env = "AMP_REVISION_POLLING_INTERVAL", default_value = "300")]
+    pub revision_polling_interval: u64,
+
+    /// The interval in seconds to prune the old images of actors,
+    /// the default is `86400`.
+    #[clap(long, env = "AMP_IMAGE_PRUNING_INTERVAL", default_value = "86400")]
+    pub image_pruning_interval: u64,
 }
@@ -15,7 +15,7 @@
 use std::sync::Arc;
 
 use amp_common::config::CredentialConfiguration;
-use amp_resources::credential;
+use amp_resources::{configuration, credential};
 use futures::{StreamExt, TryStreamExt};
 use k8s_openapi::api::core::v1::ConfigMap;
 use kube::api::ListParams;
@@ -24,6 +24,7 @@ use kube::{Api, ResourceExt};
 use tracing::{debug, error, info};
 
 use crate::context::Context;
+use crate::secret_watcher;
 
 pub async fn new(ctx: &Arc<Context>) {
     let namespace = ctx.config.namespace.clone();
@@ -31,7 +32,7 @@ pub async fn new(ctx: &Arc<Context>) {
 
     let api = Api::<ConfigMap>::namespaced(ctx.k8s.clone(), &namespace);
 
-    let params = ListParams::default().fields("metadata.name=amp-configurations");
+    let params = ListParams::default().fields(&format!("metadata.name={}", configuration::CONFIGMAP_NAME));
     let mut obs = watcher(api, params).applied_objects().boxed();
 
     loop {
@@ -60,24 +61,57 @@ async fn handle(ctx: &Arc<Context>, cm: &ConfigMap) -> anyhow::Result<()> {
         debug!("Recived configmap data is: {:?}", data);
         if let Some(content) = data.get("configuration.toml") {
             debug!("The content of configuration.toml: {:?}", content);
-            let value: CredentialConfiguration = toml::from_str(content)?;
-
-            let mut configuration = ctx.configuration.write().await;
-            *configuration = value;
-
-            // Refresh the credentials under the amp platform's own namespace.
-            debug!("Refresh the credentials under the amp platform's own namespace.");
-            credential::sync(
-                &ctx.k8s,
-                &ctx.config.namespace,
-                &ctx.config.service_account_name,
-                &configuration,
-            )
-            .await?;
-
-            info!("The latest configuration has been successfully applied!");
+
+            // The credentials and the platform configuration are parsed separately,
+            // so that an invalid one never stops the other from being applied.
+            let credentials = apply_credentials(ctx, content).await;
+            if let Err(err) = &credentials {
+                error!("Apply the credentials failed: {}", err.to_string());
+            }
+            let platform = apply_platform(ctx, content).await;
+            if let Err(err) = &platform {
+                error!("Apply the platform configuration failed: {}", err.to_string());
+            }
+
+            if credentials.is_ok() && platform.is_ok() {
+                info!("The latest configuration has been successfully applied!");
+            }
         }
     }
 
     Ok(())
 }
+
+/// Apply the credentials of registries and repositories.
+async fn apply_credentials(ctx: &Arc<Context>, content: &str) -> anyhow::Result<()> {
+    let value: CredentialConfiguration = toml::from_str(content)?;
+
+    let mut configuration = ctx.configuration.write().await;
+    *configuration = value;
+
+    // Refresh the credentials under the amp platform's own namespace.
+    debug!("Refresh the credentials under the amp platform's own namespace.");
+    credential::sync(
+        &ctx.k8s,
+        &ctx.config.namespace,
+        &ctx.config.service_account_name,
+        &configuration,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Apply the platform configuration, e.g. the options of builds.
+async fn apply_platform(ctx: &Arc<Context>, content: &str) -> anyhow::Result<()> {
+    let value = configuration::parse(content)?;
+
+    let mut platform = ctx.platform.write().await;
+    *platform = value;
+    drop(platform);
+
+    // Refresh the runtime secrets under the namespaces of playbooks.
+    secret_watcher::sync(ctx).await?;
+
+    Ok(())
+}
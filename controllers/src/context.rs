@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use amp_common::config::CredentialConfiguration;
+use amp_resources::configuration::Configuration;
 use k8s_openapi::api::core::v1::ObjectReference;
 use kube::runtime::events::Recorder;
 use kube::Client;
@@ -31,6 +32,8 @@ use crate::config::Config;
 pub struct Context {
     pub k8s: Client,
     pub configuration: RwLock<CredentialConfiguration>,
+    /// The platform configuration besides the credentials, e.g. build options.
+    pub platform: RwLock<Configuration>,
     pub config: Config,
 }
 
@@ -39,6 +42,7 @@ impl Context {
         Ok(Context {
             k8s: Client::try_default().await?,
             configuration: RwLock::new(CredentialConfiguration::default()),
+            platform: RwLock::new(Configuration::default()),
             config,
         })
     }
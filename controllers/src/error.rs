@@ -32,6 +32,9 @@ pub enum Error {
 
     #[error("DockerRegistryExistsFailed: {0}")]
     DockerRegistryExistsFailed(#[source] anyhow::Error),
+
+    #[error("SerializationError: {0}")]
+    SerializationError(#[source] serde_json::Error),
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
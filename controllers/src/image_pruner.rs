@@ -0,0 +1,105 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use amp_common::schema::Actor;
+use amp_resources::builder::kaniko;
+use amp_resources::{retention, settings};
+use kube::api::ListParams;
+use kube::{Api, ResourceExt};
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+use crate::context::Context;
+
+/// Periodically prunes the old tags in the image and cache repositories
+/// of actors, keeps the most recent ones by the retention policy.
+pub async fn new(ctx: &Arc<Context>) {
+    let interval = Duration::from_secs(ctx.config.image_pruning_interval);
+
+    loop {
+        sleep(interval).await;
+
+        if let Err(err) = prune(ctx).await {
+            error!("Prune the images failed: {}", err.to_string());
+        }
+    }
+}
+
+async fn prune(ctx: &Arc<Context>) -> anyhow::Result<()> {
+    let platform = ctx.platform.read().await.clone();
+    let keep = match platform.build.retention {
+        Some(keep) => keep,
+        None => return Ok(()),
+    };
+
+    let api = Api::<Actor>::all(ctx.k8s.clone());
+    let actors = api.list(&ListParams::default()).await?;
+
+    // Collect the repositories of actors, along with the tags in use.
+    let mut repositories: HashMap<String, HashSet<String>> = HashMap::new();
+    for actor in actors.iter() {
+        let settings = match settings::of(actor) {
+            Ok(settings) => settings,
+            Err(err) => {
+                error!(
+                    "Read the settings of actor {} failed: {}",
+                    actor.name_any(),
+                    err.to_string()
+                );
+                continue;
+            }
+        };
+
+        // The prebuilt images are not owned by us.
+        if !settings.prebuilt() {
+            let tag = actor.spec.docker_tag();
+            let tag = tag.rsplit_once(':').map_or(tag.as_str(), |(_, tag)| tag);
+            let tags = repositories.entry(actor.spec.image.clone()).or_default();
+            // The images of platforms are referenced by the manifest list.
+            for platform in &settings.build.platforms {
+                tags.insert(kaniko::platform_tag(tag, platform));
+            }
+            tags.insert(tag.to_string());
+        }
+
+        let cache_repo = settings
+            .build
+            .cache_repo
+            .or_else(|| platform.kaniko.cache_repo.clone())
+            .or_else(|| platform.buildkit.cache_repo.clone());
+        if let Some(repo) = cache_repo {
+            let tags = repositories.entry(repo).or_default();
+            tags.insert(actor.spec.build_name());
+            tags.insert(actor.spec.name.clone());
+        }
+    }
+
+    let configuration = ctx.configuration.read().await.clone();
+    let insecure_registries = &platform.kaniko.insecure_registries;
+    for (repository, protected) in repositories.iter() {
+        debug!("Prune the repository {}, keeps {:?}", repository, protected);
+        match retention::prune(&configuration, insecure_registries, repository, keep, protected).await {
+            Ok(pruned) if !pruned.is_empty() => info!("Pruned {} tags of {}", pruned.len(), repository),
+            Ok(_) => {}
+            Err(err) => error!("Prune the repository {} failed: {}", repository, err.to_string()),
+        }
+    }
+
+    debug!("Pruned the images of {} actors", actors.items.len());
+    Ok(())
+}
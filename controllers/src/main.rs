@@ -28,8 +28,11 @@ use crate::context::Context;
 
 mod actor_controller;
 mod configuration_watcher;
+mod image_pruner;
 mod namespace_watcher;
 mod playbook_controller;
+mod revision_watcher;
+mod secret_watcher;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -54,6 +57,9 @@ async fn main() -> anyhow::Result<()> {
         _ = actor_controller::new(&ctx) => tracing::warn!("actor controller exited"),
         _ = configuration_watcher::new(&ctx) => tracing::warn!("configuration watcher exited"),
         _ = namespace_watcher::new(&ctx) => tracing::warn!("namespace watcher exited"),
+        _ = secret_watcher::new(&ctx) => tracing::warn!("secret watcher exited"),
+        _ = revision_watcher::new(&ctx) => tracing::warn!("revision watcher exited"),
+        _ = image_pruner::new(&ctx) => tracing::warn!("image pruner exited"),
     }
 
     Ok(())
@@ -14,7 +14,7 @@
 
 use std::sync::Arc;
 
-use amp_resources::credential;
+use amp_resources::{credential, secret};
 use futures::{StreamExt, TryStreamExt};
 use k8s_openapi::api::core::v1::Namespace;
 use kube::api::ListParams;
@@ -62,5 +62,9 @@ async fn handle(ctx: &Arc<Context>, ns: &Namespace) -> anyhow::Result<()> {
     let configuration = ctx.configuration.read().await;
     credential::sync(&ctx.k8s, &ns.name_any(), "default", &configuration).await?;
 
+    // Copy the runtime secrets of platform for the actors in this namespace.
+    let platform = ctx.platform.read().await;
+    secret::sync(&ctx.k8s, &ctx.config.namespace, &ns.name_any(), &platform.secrets).await?;
+
     Ok(())
 }
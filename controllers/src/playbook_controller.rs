@@ -18,8 +18,10 @@ use std::time::Duration;
 
 use amp_common::schema::{Playbook, PlaybookState, Source};
 use amp_resolver as resolver;
+use amp_resolver::lock::Lock;
 use amp_resources::event::trace;
-use amp_resources::{actor, namespace, playbook};
+use amp_resources::settings::SETTINGS_ANNOTATION_KEY;
+use amp_resources::{actor, annotation, namespace, network_policy, playbook};
 use futures::{future, StreamExt};
 use k8s_openapi::api::core::v1::ObjectReference;
 use kube::api::ListParams;
@@ -94,6 +96,9 @@ async fn init(playbook: &Playbook, ctx: &Arc<Context>, recorder: &Recorder) -> R
     namespace::create(&ctx.k8s, playbook).await?;
     trace(recorder, "Created namespace for this playbook").await?;
 
+    // Isolate the namespace, only the partners of actors can reach them.
+    network_policy::isolate(&ctx.k8s, playbook).await?;
+
     trace(recorder, "Init successfully, Let's begin resolving, now!").await?;
     playbook::patch_status(&ctx.k8s, playbook, PlaybookState::resolving()).await?;
 
@@ -107,6 +112,11 @@ async fn resolve(playbook: &Playbook, ctx: &Arc<Context>, recorder: &Recorder) -
         let exists: HashSet<&String> = actors.iter().map(|actor| &actor.name).collect();
 
         for actor in actors {
+            // The revision was cleared to re-resolve this actor (e.g. update action).
+            if actor.source.rev.is_none() {
+                fetches.insert(actor.source.clone());
+            }
+
             if let Some(partners) = &actor.partners {
                 for (name, partner) in partners {
                     if exists.contains(name) {
@@ -125,15 +135,43 @@ async fn resolve(playbook: &Playbook, ctx: &Arc<Context>, recorder: &Recorder) -
 
     tracing::debug!("The repositories to be fetched are: {fetches:?}");
     let configuration = ctx.configuration.read().await;
+    let variables = playbook::variables(playbook).map_err(Error::ResourceError)?;
+    let overrides = playbook::overrides(playbook).map_err(Error::ResourceError)?;
+    let mut lock: Lock = annotation(playbook, playbook::LOCK_ANNOTATION_KEY).map_err(Error::ResourceError)?;
+    let mut settings = playbook::settings(playbook).map_err(Error::ResourceError)?;
 
+    let mut actors = vec![];
     for source in fetches.iter() {
+        // Refuse to move to the newer commits silently, pin it to the locked revision.
+        let source = match playbook::locked(playbook) {
+            true => lock.pin(source),
+            false => source.clone(),
+        };
+
         tracing::info!("fetching partner with source: {}", source.uri());
-        let actor = resolver::load(&configuration, source).map_err(Error::ResolveError)?;
+        let items = resolver::load_all(&configuration, &source, &variables, &overrides).map_err(Error::ResolveError)?;
 
-        let message = "Fetch and add the actor to this playbook";
+        let message = format!("Fetched {} actor(s) from {}", items.len(), source.uri());
         trace(recorder, message).await.map_err(Error::ResourceError)?;
 
-        playbook::add(&ctx.k8s, playbook, actor)
+        for item in items {
+            lock.actors.insert(item.spec.name.clone(), item.lock);
+            settings.insert(item.spec.name.clone(), item.settings);
+            actors.push(item.spec);
+        }
+    }
+
+    if !actors.is_empty() {
+        let message = "Add the fetched actors to this playbook";
+        trace(recorder, message).await.map_err(Error::ResourceError)?;
+
+        playbook::annotate(&ctx.k8s, playbook, SETTINGS_ANNOTATION_KEY, &settings)
+            .await
+            .map_err(Error::ResourceError)?;
+        playbook::annotate(&ctx.k8s, playbook, playbook::LOCK_ANNOTATION_KEY, &lock)
+            .await
+            .map_err(Error::ResourceError)?;
+        playbook::add(&ctx.k8s, playbook, actors)
             .await
             .map_err(Error::ResourceError)?;
     }
@@ -169,6 +207,11 @@ async fn run(playbook: &Playbook, ctx: &Arc<Context>, recorder: &Recorder) -> Re
             }
         }
     }
+
+    // Allow the traffic between partners and from the ingress controller.
+    let ingress = ctx.platform.read().await.ingress.clone();
+    network_policy::allow(&ctx.k8s, playbook, ingress.as_ref()).await?;
+
     Ok(())
 }
 
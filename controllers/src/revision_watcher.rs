@@ -0,0 +1,157 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use amp_common::config::CredentialConfiguration;
+use amp_common::schema::{Playbook, Source};
+use amp_resolver as resolver;
+use amp_resolver::lock::Lock;
+use amp_resolver::ResolvedActor;
+use amp_resources::playbook::LOCK_ANNOTATION_KEY;
+use amp_resources::settings::SETTINGS_ANNOTATION_KEY;
+use amp_resources::{annotation, playbook};
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::{Api, ResourceExt};
+use serde_json::{json, to_string, Value};
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+use crate::context::Context;
+
+/// The minimum interval in seconds between two polls, so that the repositories
+/// are not hammered by a misconfigured interval.
+const MIN_POLLING_INTERVAL: u64 = 30;
+
+/// Periodically polls the branches tracked by actors, and moves them to the
+/// latest commits, so that they are rebuilt and redeployed.
+pub async fn new(ctx: &Arc<Context>) {
+    let interval = Duration::from_secs(ctx.config.revision_polling_interval.max(MIN_POLLING_INTERVAL));
+
+    loop {
+        sleep(interval).await;
+
+        if let Err(err) = poll(ctx).await {
+            error!("Poll the revisions failed: {}", err.to_string());
+        }
+    }
+}
+
+async fn poll(ctx: &Arc<Context>) -> anyhow::Result<()> {
+    let api = Api::<Playbook>::all(ctx.k8s.clone());
+    let playbooks = api.list(&ListParams::default()).await?;
+
+    for playbook in playbooks.iter() {
+        // Leave the locked playbook until it's updated explicitly.
+        if playbook::locked(playbook) {
+            continue;
+        }
+
+        if let Err(err) = handle(ctx, playbook).await {
+            error!(
+                "Poll the revisions of {} failed: {}",
+                playbook.name_any(),
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// This function moves the actors of playbook to the latest commits of their branches.
+async fn handle(ctx: &Arc<Context>, playbook: &Playbook) -> anyhow::Result<()> {
+    let actors = match &playbook.spec.actors {
+        Some(actors) => actors,
+        None => return Ok(()),
+    };
+
+    // Take a copy, so that the configuration is not locked while talking to the repositories.
+    let configuration = ctx.configuration.read().await.clone();
+    let variables = playbook::variables(playbook)?;
+    let overrides = playbook::overrides(playbook)?;
+
+    let mut changes = vec![];
+    for actor in actors {
+        match revise(&configuration, &actor.source, &variables, &overrides) {
+            Ok(items) => changes.extend(items),
+            Err(err) => error!("Poll the revision of actor {} failed: {}", actor.name, err.to_string()),
+        }
+    }
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    let mut lock: Lock = annotation(playbook, LOCK_ANNOTATION_KEY)?;
+    let mut settings = playbook::settings(playbook)?;
+    let mut actors = actors.clone();
+    for item in changes {
+        lock.actors.insert(item.spec.name.clone(), item.lock);
+        settings.insert(item.spec.name.clone(), item.settings);
+        match actors.iter_mut().find(|actor| actor.name == item.spec.name) {
+            Some(actor) => *actor = item.spec,
+            None => actors.push(item.spec),
+        }
+    }
+
+    // The resource version rejects the patch if the playbook has been modified
+    // since it was listed, the changes are picked up again by the next poll.
+    let patch = json!({
+        "metadata": {
+            "resourceVersion": playbook.resource_version(),
+            "annotations": {
+                SETTINGS_ANNOTATION_KEY: to_string(&settings)?,
+                LOCK_ANNOTATION_KEY: to_string(&lock)?,
+            }
+        },
+        "spec": { "actors": actors }
+    });
+
+    let api = Api::<Playbook>::all(ctx.k8s.clone());
+    let name = playbook.name_any();
+    api.patch(&name, &PatchParams::apply("amp-controllers"), &Patch::Merge(&patch))
+        .await?;
+
+    info!("Moved the actors of {} to the latest commits", name);
+
+    Ok(())
+}
+
+/// Reload the actor at the latest commit of its branch, the manifest may have
+/// changed as well. Nothing is returned if the actor is up to date.
+fn revise(
+    configuration: &CredentialConfiguration,
+    source: &Source,
+    variables: &HashMap<String, String>,
+    overrides: &HashMap<String, Value>,
+) -> anyhow::Result<Vec<ResolvedActor>> {
+    let rev = match resolver::latest(configuration, source)? {
+        Some(rev) => rev,
+        None => return Ok(vec![]),
+    };
+
+    if source.rev.as_ref() == Some(&rev) {
+        debug!("The source {} is up to date with {}", source.repo, rev);
+        return Ok(vec![]);
+    }
+
+    info!("Found new commit {} for {}", rev, source.repo);
+    let mut source = source.clone();
+    source.rev = Some(rev);
+
+    Ok(resolver::load_all(configuration, &source, variables, overrides)?)
+}
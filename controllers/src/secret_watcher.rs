@@ -0,0 +1,76 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use amp_resources::secret;
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Namespace, Secret};
+use kube::api::ListParams;
+use kube::runtime::watcher::{watcher, Event};
+use kube::{Api, ResourceExt};
+use tracing::{error, info};
+
+use crate::context::Context;
+
+/// Watch the runtime secrets of platform in the namespace of Amphitheatre,
+/// so that the rotated or deleted ones are synchronized into the playbooks.
+pub async fn new(ctx: &Arc<Context>) {
+    let api = Api::<Secret>::namespaced(ctx.k8s.clone(), &ctx.config.namespace);
+    let mut obs = watcher(api, ListParams::default()).boxed();
+
+    loop {
+        let event = obs.try_next().await;
+
+        match event {
+            Ok(Some(event)) => {
+                let names: Vec<String> = match event {
+                    Event::Applied(secret) | Event::Deleted(secret) => vec![secret.name_any()],
+                    Event::Restarted(secrets) => secrets.iter().map(|secret| secret.name_any()).collect(),
+                };
+
+                // Ignore the secrets which are not declared as the runtime secrets.
+                let declared = ctx.platform.read().await.secrets.clone();
+                if !names.iter().any(|name| declared.contains(name)) {
+                    continue;
+                }
+
+                info!("Handle the changed runtime secrets: {:?}", names);
+                if let Err(err) = sync(ctx).await {
+                    error!("Sync runtime secrets failed: {}", err.to_string());
+                }
+            }
+            Ok(None) => continue,
+            Err(err) => {
+                error!("Resolve secret stream failed: {}", err.to_string());
+                continue;
+            }
+        }
+    }
+}
+
+/// Copy the runtime secrets of platform into the namespaces of playbooks.
+pub async fn sync(ctx: &Arc<Context>) -> anyhow::Result<()> {
+    let secrets = ctx.platform.read().await.secrets.clone();
+
+    let api = Api::<Namespace>::all(ctx.k8s.clone());
+    let params = ListParams::default().labels("syncer.amphitheatre.app/sync=true");
+    for ns in api.list(&params).await? {
+        if ns.status.as_ref().and_then(|status| status.phase.as_deref()) == Some("Terminating") {
+            continue;
+        }
+        secret::sync(&ctx.k8s, &ctx.config.namespace, &ns.name_any(), &secrets).await?;
+    }
+
+    Ok(())
+}
@@ -38,6 +38,12 @@ pub enum ResolveError {
 
     #[error("SCMError")]
     SCMError(#[source] SCMError),
+
+    #[error("UndefinedVariable: {0}")]
+    UndefinedVariable(String),
+
+    #[error("OverrideFailed: {0}")]
+    OverrideFailed(#[source] serde_json::Error),
 }
 
 pub type Result<T, E = ResolveError> = std::result::Result<T, E>;
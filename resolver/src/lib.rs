@@ -12,14 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap};
+
 use amp_common::config::{Credential, CredentialConfiguration};
 use amp_common::schema::{ActorSpec, Manifest, Source};
 use amp_common::scm::client::Client;
+use amp_resources::settings::{self, ConfigSettings, Settings};
 use errors::{ResolveError, Result};
+use lock::LockEntry;
+use serde_json::Value;
 use tracing::debug;
 use url::Url;
+use workspace::WorkspaceManifest;
 
 pub mod errors;
+pub mod lock;
+pub mod overrides;
+pub mod vars;
+pub mod workspace;
+
+/// The actor loaded from its manifest, along with its settings and
+/// the lock entry that pins its resolved revision and manifest.
+#[derive(Clone, Debug)]
+pub struct ResolvedActor {
+    pub spec: ActorSpec,
+    pub settings: Settings,
+    pub lock: LockEntry,
+}
 
 /// Resolve the repo from the URL.
 fn repo(url: &str) -> Result<String> {
@@ -63,34 +82,152 @@ fn patch(client: &Client, source: &Source) -> Result<Source> {
     }
 
     // Get its real latest revision according to the reference
-    let commit = client.git().find_commit(&repo, &reference).unwrap();
-    actual.rev = Some(commit.unwrap().sha);
+    actual.rev = Some(commit(client, &repo, &reference)?);
 
     Ok(actual)
 }
 
-/// Read real actor information from remote VCS (like github).
-pub fn load(configuration: &CredentialConfiguration, source: &Source) -> Result<ActorSpec> {
+/// Find the latest commit SHA of the reference.
+fn commit(client: &Client, repo: &str, reference: &str) -> Result<String> {
+    let commit = client
+        .git()
+        .find_commit(repo, reference)
+        .map_err(|e| ResolveError::FetchingError(e.to_string()))?;
+
+    commit
+        .map(|commit| commit.sha)
+        .ok_or_else(|| ResolveError::FetchingError(format!("No commit found for {} in {}", reference, repo)))
+}
+
+/// Find the latest revision of the branch tracked by the source,
+/// returns none if the source is not tracking a branch (e.g. tag).
+pub fn latest(configuration: &CredentialConfiguration, source: &Source) -> Result<Option<String>> {
+    let branch = match (&source.tag, &source.branch) {
+        (None, Some(branch)) => branch,
+        _ => return Ok(None),
+    };
+
+    let client = Client::init(configuration, source).map_err(ResolveError::SCMError)?;
+    let repo = repo(&source.repo)?;
+
+    Ok(Some(commit(&client, &repo, branch)?))
+}
+
+/// Read real actor information from remote VCS (like github),
+/// the `${var}` placeholders in manifest are resolved from the playbook-level
+/// variables, and the overrides for this actor are merged onto the spec.
+pub fn load(
+    configuration: &CredentialConfiguration,
+    source: &Source,
+    variables: &HashMap<String, String>,
+    overrides: &HashMap<String, Value>,
+) -> Result<ActorSpec> {
+    // Initialize the client by source host.
+    let client = Client::init(configuration, source).map_err(ResolveError::SCMError)?;
+    let source = patch(&client, source)?;
+
+    let content = read(&client, &source, variables)?;
+    Ok(build(configuration, &content, source, overrides)?.spec)
+}
+
+/// Read all the characters declared by the source, a monorepo declares its
+/// characters as the members of `[workspace]`, otherwise it's the only one.
+pub fn load_all(
+    configuration: &CredentialConfiguration,
+    source: &Source,
+    variables: &HashMap<String, String>,
+    overrides: &HashMap<String, Value>,
+) -> Result<Vec<ResolvedActor>> {
     // Initialize the client by source host.
     let client = Client::init(configuration, source).map_err(ResolveError::SCMError)?;
     let source = patch(&client, source)?;
+
+    let content = read(&client, &source, variables)?;
+    let manifest: WorkspaceManifest =
+        toml::from_str(&content).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
+
+    let workspace = match manifest.workspace {
+        Some(workspace) => workspace,
+        None => {
+            return Ok(vec![build(configuration, &content, source, overrides)?]);
+        }
+    };
+
+    let mut actors = vec![];
+    for member in workspace.members.iter() {
+        // All the members are pinned to the same revision of the repository.
+        let mut actual = source.clone();
+        actual.path = Some(workspace::join(source.path.as_deref(), member));
+        debug!("Read the member {} of workspace {}", member, source.repo);
+
+        let content = read(&client, &actual, variables)?;
+        let mut actor = build(configuration, &content, actual, overrides)?;
+        // Lock the members for the workspace, so that it's pinned as well.
+        actor.lock.workspace = Some(source.path.clone().unwrap_or_default());
+        actors.push(actor);
+    }
+
+    Ok(actors)
+}
+
+/// Fetch the files of the config declared by the actor at the revision of
+/// its source, the contents are keyed by the base names of files.
+pub fn files(
+    configuration: &CredentialConfiguration,
+    source: &Source,
+    config: &ConfigSettings,
+) -> Result<BTreeMap<String, String>> {
+    let client = Client::init(configuration, source).map_err(ResolveError::SCMError)?;
+    let repo = repo(&source.repo)?;
+
+    let mut data = BTreeMap::new();
+    for file in config.files.iter() {
+        let path = workspace::join(source.path.as_deref(), file);
+        let content = client
+            .contents()
+            .find(&repo, &path, source.rev())
+            .map_err(|e| ResolveError::FetchingError(e.to_string()))?;
+        let content = std::str::from_utf8(&content.data)
+            .map_err(|_| ResolveError::FetchingError(format!("The config file {} is not UTF-8", path)))?;
+
+        let name = file.rsplit('/').next().unwrap_or(file);
+        data.insert(name.to_string(), content.to_string());
+    }
+
+    Ok(data)
+}
+
+/// Fetch the manifest of the source, and resolve its `${var}` placeholders.
+fn read(client: &Client, source: &Source, variables: &HashMap<String, String>) -> Result<String> {
     let repo = repo(&source.repo)?;
-    let path = source.path.clone().unwrap_or(".amp.toml".into());
+    let path = workspace::manifest(source.path.as_deref());
 
     let content = client
         .contents()
         .find(&repo, &path, source.rev())
         .map_err(|e| ResolveError::FetchingError(e.to_string()))?;
-    debug!("The `.amp.toml` content of {} is:\n{:?}", repo, content);
+    debug!("The `{}` content of {} is:\n{:?}", path, repo, content);
+
+    let content = std::str::from_utf8(&content.data).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
+    vars::interpolate(content, variables)
+}
 
-    let manifest: Manifest =
-        toml::from_slice(content.data.as_slice()).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
+/// Build the actor spec from the manifest content.
+fn build(
+    configuration: &CredentialConfiguration,
+    content: &str,
+    source: Source,
+    overrides: &HashMap<String, Value>,
+) -> Result<ResolvedActor> {
+    let manifest: Manifest = toml::from_str(content).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
+
+    let settings = settings::parse(content).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
 
     let mut spec = ActorSpec::from(&manifest);
     spec.source = source;
-
-    // Generate image name based on the current registry and character name.
-    if manifest.character.image.is_none() {
+    // Generate image name based on the current registry and character name,
+    // the prebuilt image is never pushed to the registry.
+    if manifest.character.image.is_none() && !settings.prebuilt() {
         if let Some(credential) = configuration.default_registry() {
             let mut registry = credential.server.as_str();
             if registry.eq("https://index.docker.io/v1/") {
@@ -103,5 +240,12 @@ pub fn load(configuration: &CredentialConfiguration, source: &Source) -> Result<
         }
     }
 
-    Ok(spec)
+    if let Some(value) = overrides.get(&spec.name) {
+        debug!("Merge the overrides onto actor {}: {:?}", spec.name, value);
+        spec = overrides::merge(&spec, value)?;
+    }
+
+    let lock = LockEntry::new(&spec, content);
+
+    Ok(ResolvedActor { spec, settings, lock })
 }
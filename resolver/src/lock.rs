@@ -0,0 +1,175 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::{ActorSpec, Source};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The lock of playbook, which pins the revisions and manifests of the
+/// actors resolved from repositories, keyed by actor name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Lock {
+    pub actors: BTreeMap<String, LockEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct LockEntry {
+    /// The repository of the actor.
+    pub repo: String,
+    /// The sub-path of the actor in repository.
+    pub path: Option<String>,
+    /// The path of the workspace which declared the actor as a member,
+    /// the workspace is pinned to the same revision as its members.
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// The tag or branch tracked by the actor, none if the revision was pinned.
+    pub reference: Option<String>,
+    /// The resolved commit SHA.
+    pub rev: String,
+    /// The SHA256 of the manifest.
+    pub manifest: String,
+    /// When the actor was resolved.
+    pub resolved_at: Time,
+}
+
+impl LockEntry {
+    pub fn new(spec: &ActorSpec, manifest: &str) -> Self {
+        let source = &spec.source;
+
+        Self {
+            repo: source.repo.clone(),
+            path: source.path.clone(),
+            workspace: None,
+            reference: source.tag.clone().or_else(|| source.branch.clone()),
+            rev: source.rev().to_string(),
+            manifest: format!("{:x}", Sha256::digest(manifest)),
+            resolved_at: Time(Utc::now()),
+        }
+    }
+
+    /// Check if the entry is locked for the source, the source is either
+    /// the actor itself or the workspace which declared it.
+    fn matches(&self, source: &Source) -> bool {
+        self.repo == source.repo
+            && (normalize(&self.path) == normalize(&source.path)
+                || self.workspace.is_some() && normalize(&self.workspace) == normalize(&source.path))
+    }
+}
+
+impl Lock {
+    /// Pin the source to the locked revision, if there is one, the revision
+    /// declared by the source explicitly takes precedence over the locked one.
+    pub fn pin(&self, source: &Source) -> Source {
+        let mut actual = source.clone();
+        if source.rev.is_some() {
+            return actual;
+        }
+
+        if let Some(entry) = self.actors.values().find(|entry| entry.matches(source)) {
+            actual.rev = Some(entry.rev.clone());
+        }
+
+        actual
+    }
+}
+
+#[inline]
+fn normalize(path: &Option<String>) -> &str {
+    path.as_deref().unwrap_or_default().trim_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn source(repo: &str, path: Option<&str>) -> Source {
+        serde_json::from_value(json!({ "repo": repo, "path": path })).unwrap()
+    }
+
+    fn entry(repo: &str, path: Option<&str>, workspace: Option<&str>, rev: &str) -> LockEntry {
+        LockEntry {
+            repo: repo.into(),
+            path: path.map(Into::into),
+            workspace: workspace.map(Into::into),
+            reference: Some("main".into()),
+            rev: rev.into(),
+            manifest: String::new(),
+            resolved_at: Time(Utc::now()),
+        }
+    }
+
+    fn lock(entries: Vec<(&str, LockEntry)>) -> Lock {
+        Lock {
+            actors: entries
+                .into_iter()
+                .map(|(name, entry)| (name.to_string(), entry))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_pin_actor_source() {
+        let lock = lock(vec![(
+            "api",
+            entry("https://github.com/a/b", Some("/api/"), None, "abc"),
+        )]);
+
+        let pinned = lock.pin(&source("https://github.com/a/b", Some("api")));
+        assert_eq!(pinned.rev.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn test_pin_declared_revision() {
+        let lock = lock(vec![("api", entry("https://github.com/a/b", Some("api"), None, "abc"))]);
+
+        let mut declared = source("https://github.com/a/b", Some("api"));
+        declared.rev = Some("def".into());
+        assert_eq!(lock.pin(&declared).rev.as_deref(), Some("def"));
+    }
+
+    #[test]
+    fn test_pin_workspace_root_source() {
+        let lock = lock(vec![
+            ("api", entry("https://github.com/a/b", Some("api"), Some(""), "abc")),
+            ("web", entry("https://github.com/a/b", Some("web"), Some(""), "abc")),
+        ]);
+
+        assert_eq!(
+            lock.pin(&source("https://github.com/a/b", None)).rev.as_deref(),
+            Some("abc")
+        );
+        assert_eq!(
+            lock.pin(&source("https://github.com/a/b", Some("/"))).rev.as_deref(),
+            Some("abc")
+        );
+    }
+
+    #[test]
+    fn test_pin_unlocked_source() {
+        let lock = lock(vec![
+            ("api", entry("https://github.com/a/b", Some("api"), None, "abc")),
+            ("db", entry("https://github.com/a/c", None, None, "def")),
+        ]);
+
+        // The actor is not a member of workspace, the root source is not locked by it.
+        assert_eq!(lock.pin(&source("https://github.com/a/b", None)).rev, None);
+        assert_eq!(lock.pin(&source("https://github.com/a/d", None)).rev, None);
+    }
+}
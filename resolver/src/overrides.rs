@@ -0,0 +1,51 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::schema::ActorSpec;
+use serde_json::{from_value, to_value, Value};
+
+use crate::errors::{ResolveError, Result};
+
+/// Merge the playbook-level overrides onto the actor spec, following the
+/// semantics of JSON Merge Patch (RFC 7386): objects are merged recursively,
+/// `null` removes the field and any other value replaces it.
+pub fn merge(spec: &ActorSpec, overrides: &Value) -> Result<ActorSpec> {
+    let mut target = to_value(spec).map_err(ResolveError::OverrideFailed)?;
+    patch(&mut target, overrides);
+
+    from_value(target).map_err(ResolveError::OverrideFailed)
+}
+
+fn patch(target: &mut Value, overrides: &Value) {
+    let fields = match overrides {
+        Value::Object(fields) => fields,
+        _ => {
+            *target = overrides.clone();
+            return;
+        }
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+
+    let map = target.as_object_mut().unwrap();
+    for (key, value) in fields {
+        if value.is_null() {
+            map.remove(key);
+        } else {
+            patch(map.entry(key.as_str()).or_insert(Value::Null), value);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use toml::Value;
+
+use crate::errors::{ResolveError, Result};
+
+/// Replace the `${name}` placeholders in the string values of manifest with
+/// the playbook-level variables, `$${name}` escapes a literal `${name}`.
+///
+/// The manifest is parsed before the interpolation, so that the values are
+/// never able to break the TOML syntax, and the placeholders in comments
+/// or keys are left untouched (the comments are dropped from the output).
+///
+/// Only the string values are interpolated, so the non-string fields, e.g.
+/// `replicas`, can't be parameterized: a bare `${name}` is not valid TOML, and
+/// `"${name}"` stays a string after the interpolation.
+pub fn interpolate(content: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let mut manifest: Value = toml::from_str(content).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))?;
+    walk(&mut manifest, variables)?;
+
+    toml::to_string(&manifest).map_err(|e| ResolveError::TomlParseFailed(e.to_string()))
+}
+
+/// Interpolate the string values in the value recursively.
+fn walk(value: &mut Value, variables: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::String(text) => *text = substitute(text, variables)?,
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                walk(item, variables)?;
+            }
+        }
+        Value::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                walk(item, variables)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Replace the `${name}` placeholders in the text.
+fn substitute(text: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("$${") {
+            output.push('$');
+            rest = &rest[2..];
+            continue;
+        }
+
+        if !rest.starts_with("${") {
+            output.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+
+        // Leave the unterminated placeholder as it is.
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let name = rest[2..end].trim();
+        let value = variables
+            .get(name)
+            .ok_or_else(|| ResolveError::UndefinedVariable(name.to_string()))?;
+        output.push_str(value);
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("host".to_string(), "db.local".to_string()),
+            ("quoted".to_string(), r#"say "hi" \o/"#.to_string()),
+        ])
+    }
+
+    fn parse(content: &str) -> Value {
+        toml::from_str(content).unwrap()
+    }
+
+    #[test]
+    fn test_interpolate_string_values() {
+        let content = "[character]\nname = \"app\"\nurl = \"postgres://${ host }/app\"\n";
+        let output = interpolate(content, &variables()).unwrap();
+
+        assert_eq!(
+            parse(&output)["character"]["url"].as_str(),
+            Some("postgres://db.local/app")
+        );
+    }
+
+    #[test]
+    fn test_interpolate_escapes_values() {
+        let content = "[character]\nmessage = \"${quoted}\"\n";
+        let output = interpolate(content, &variables()).unwrap();
+
+        assert_eq!(parse(&output)["character"]["message"].as_str(), Some(r#"say "hi" \o/"#));
+    }
+
+    #[test]
+    fn test_interpolate_arrays_and_nested_tables() {
+        let content = "[deploy]\nargs = [\"--host=${host}\"]\n[deploy.env]\nHOST = \"${host}\"\n";
+        let output = parse(&interpolate(content, &variables()).unwrap());
+
+        assert_eq!(output["deploy"]["args"][0].as_str(), Some("--host=db.local"));
+        assert_eq!(output["deploy"]["env"]["HOST"].as_str(), Some("db.local"));
+    }
+
+    #[test]
+    fn test_interpolate_ignores_comments() {
+        let content = "# uses ${undefined}\n[character]\nname = \"app\"\n";
+        let output = interpolate(content, &variables()).unwrap();
+
+        assert_eq!(parse(&output)["character"]["name"].as_str(), Some("app"));
+        assert!(!output.contains("undefined"));
+    }
+
+    #[test]
+    fn test_interpolate_only_string_values() {
+        let variables = HashMap::from([("replicas".to_string(), "3".to_string())]);
+
+        let output = interpolate("[deploy]\nreplicas = \"${replicas}\"\n", &variables).unwrap();
+        assert_eq!(parse(&output)["deploy"]["replicas"].as_str(), Some("3"));
+
+        assert!(matches!(
+            interpolate("[deploy]\nreplicas = ${replicas}\n", &variables),
+            Err(ResolveError::TomlParseFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_interpolate_undefined_variable() {
+        let content = "[character]\nname = \"${undefined}\"\n";
+
+        assert!(matches!(
+            interpolate(content, &variables()),
+            Err(ResolveError::UndefinedVariable(name)) if name == "undefined"
+        ));
+    }
+
+    #[test]
+    fn test_substitute_escaped_and_unterminated_placeholders() {
+        assert_eq!(substitute("$${host} and $5", &variables()).unwrap(), "${host} and $5");
+        assert_eq!(substitute("${host", &variables()).unwrap(), "${host");
+    }
+}
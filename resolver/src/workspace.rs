@@ -0,0 +1,78 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_resources::sub_path;
+use serde::Deserialize;
+
+/// The file name of the manifest, which is placed at the root of the
+/// character's sub-path in repository.
+const MANIFEST_FILE_NAME: &str = ".amp.toml";
+
+/// The manifest of a monorepo, it declares the characters of the repository
+/// in the `[workspace]` table, instead of the `[character]` table.
+#[derive(Debug, Default, Deserialize)]
+pub struct WorkspaceManifest {
+    pub workspace: Option<Workspace>,
+}
+
+/// The `[workspace]` table of manifest.
+#[derive(Debug, Default, Deserialize)]
+pub struct Workspace {
+    /// The sub-paths of the characters, relative to the workspace, each
+    /// sub-path has its own manifest and is used as the build context.
+    pub members: Vec<String>,
+}
+
+/// Join the member to the sub-path of workspace.
+pub fn join(base: Option<&str>, member: &str) -> String {
+    let member = member.trim_matches('/');
+
+    match sub_path(base) {
+        Some(base) => format!("{}/{}", base, member),
+        None => member.to_string(),
+    }
+}
+
+/// The path of the manifest file for the path of source, the path is either
+/// the sub-path of character (a directory), or the manifest file itself if it
+/// ends with `.toml`, which is kept for the sources declared before workspaces.
+pub fn manifest(path: Option<&str>) -> String {
+    match path.map(|p| p.trim_matches('/')) {
+        Some(path) if path.ends_with(".toml") => path.to_string(),
+        Some(path) if !path.is_empty() => format!("{}/{}", path, MANIFEST_FILE_NAME),
+        _ => MANIFEST_FILE_NAME.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        assert_eq!(manifest(None), ".amp.toml");
+        assert_eq!(manifest(Some("/")), ".amp.toml");
+        assert_eq!(manifest(Some("/services/api/")), "services/api/.amp.toml");
+        assert_eq!(manifest(Some(".amp.toml")), ".amp.toml");
+        assert_eq!(manifest(Some("services/api/amp.toml")), "services/api/amp.toml");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join(None, "/api/"), "api");
+        assert_eq!(join(Some("services"), "api"), "services/api");
+        assert_eq!(join(Some("services/.amp.toml"), "api"), "services/api");
+        assert_eq!(join(Some(".amp.toml"), "api"), "api");
+    }
+}
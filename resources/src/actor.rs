@@ -13,12 +13,18 @@
 // limitations under the License.
 
 use amp_common::schema::{Actor, ActorSpec, ActorState, Playbook};
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
-use kube::api::{Patch, PatchParams, PostParams};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono::Utc;
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
 use kube::{Api, Client, Resource, ResourceExt};
-use serde_json::json;
+use serde_json::{json, to_string};
 
 use super::error::{Error, Result};
+use super::playbook;
+use super::settings::{self, SETTINGS_ANNOTATION_KEY};
+
+/// The UID of actor, so that it can be found by its UID in all namespaces.
+pub const UID_LABEL_KEY: &str = "amphitheatre.app/uid";
 
 pub async fn exists(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> Result<bool> {
     let namespace = playbook.spec.namespace.clone();
@@ -32,11 +38,7 @@ pub async fn create(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
     let namespace = playbook.spec.namespace.clone();
     let api: Api<Actor> = Api::namespaced(client.clone(), namespace.as_str());
 
-    let name = spec.name.clone();
-    let mut resource = Actor::new(&name, spec.clone());
-    resource
-        .owner_references_mut()
-        .push(playbook.controller_owner_ref(&()).unwrap());
+    let resource = new(playbook, spec)?;
     tracing::debug!("The Actor resource:\n {:?}\n", resource);
 
     let actor = api
@@ -46,6 +48,9 @@ pub async fn create(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
 
     tracing::info!("Created Actor: {}", actor.name_any());
 
+    // The UID is assigned by the server, label the actor with it afterwards.
+    let actor = label(client, &actor).await?;
+
     // Patch this actor as initial Pending status
     patch_status(client, &actor, ActorState::pending()).await?;
     Ok(actor)
@@ -59,11 +64,10 @@ pub async fn update(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
     let mut actor = api.get(&name).await.map_err(Error::KubeError)?;
     tracing::debug!("The Actor {} already exists: {:?}", &spec.name, actor);
 
-    if &actor.spec != spec {
-        let mut resource = Actor::new(&name, spec.clone());
-        resource
-            .owner_references_mut()
-            .push(playbook.controller_owner_ref(&()).unwrap());
+    let resource = new(playbook, spec)?;
+    if &actor.spec != spec || settings::of(&actor)? != settings::of(&resource)? {
+        // Rebuild the image if the source has moved to another revision.
+        let rebuild = actor.spec.source.rev != spec.source.rev;
         tracing::debug!("The updating Actor resource:\n {:?}\n", resource);
 
         actor = api
@@ -76,11 +80,57 @@ pub async fn update(client: &Client, playbook: &Playbook, spec: &ActorSpec) -> R
             .map_err(Error::KubeError)?;
 
         tracing::info!("Updated Actor: {}", actor.name_any());
+
+        if rebuild {
+            patch_status(client, &actor, ActorState::building()).await?;
+        }
+    }
+
+    // The actors created before they were labelled with UID.
+    if !actor.labels().contains_key(UID_LABEL_KEY) {
+        actor = label(client, &actor).await?;
     }
 
     Ok(actor)
 }
 
+/// Label the actor with its UID.
+async fn label(client: &Client, actor: &Actor) -> Result<Actor> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let uid = actor.uid().ok_or_else(|| Error::MissingObjectKey(".metadata.uid"))?;
+
+    let api: Api<Actor> = Api::namespaced(client.clone(), &namespace);
+    let patch = json!({"metadata": { "labels": { UID_LABEL_KEY: uid }}});
+    api.patch(
+        actor.name_any().as_str(),
+        &PatchParams::apply("amp-controllers"),
+        &Patch::Merge(&patch),
+    )
+    .await
+    .map_err(Error::KubeError)
+}
+
+fn new(playbook: &Playbook, spec: &ActorSpec) -> Result<Actor> {
+    let mut settings = playbook::settings(playbook)?.remove(&spec.name).unwrap_or_default();
+    // The cache repository of playbook takes precedence over the manifest.
+    if let Some(repo) = playbook::cache_repo(playbook) {
+        settings.build.cache_repo = Some(repo);
+    }
+
+    let mut resource = Actor::new(&spec.name, spec.clone());
+    resource
+        .owner_references_mut()
+        .push(playbook.controller_owner_ref(&()).unwrap());
+    resource.annotations_mut().insert(
+        SETTINGS_ANNOTATION_KEY.into(),
+        to_string(&settings).map_err(Error::SerializationError)?,
+    );
+
+    Ok(resource)
+}
+
 pub async fn patch_status(client: &Client, actor: &Actor, condition: Condition) -> Result<()> {
     let namespace = actor
         .namespace()
@@ -102,3 +152,63 @@ pub async fn patch_status(client: &Client, actor: &Actor, condition: Condition)
 
     Ok(())
 }
+
+/// Set the annotation of actor, it's merged with the existing annotations.
+pub async fn annotate(client: &Client, actor: &Actor, key: &str, value: &str) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Actor> = Api::namespaced(client.clone(), &namespace);
+
+    let patch = json!({ "metadata": { "annotations": { key: value } } });
+    api.patch(&actor.name_any(), &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Annotated Actor {} with {}: {}", actor.name_any(), key, value);
+
+    Ok(())
+}
+
+/// The Failed condition of actor, such as the build timed out or was cancelled.
+pub fn failed(reason: &str, message: impl Into<String>) -> Condition {
+    Condition {
+        type_: "Failed".into(),
+        status: "True".into(),
+        reason: reason.into(),
+        message: message.into(),
+        last_transition_time: Time(Utc::now()),
+        observed_generation: None,
+    }
+}
+
+/// The reason of the Running condition of actor, e.g. `Deploying` or `Ready`.
+pub fn running_reason(actor: &Actor) -> Option<String> {
+    let status = serde_json::to_value(actor.status.as_ref()?).ok()?;
+    let conditions: Vec<Condition> = serde_json::from_value(status.get("conditions")?.clone()).ok()?;
+
+    conditions
+        .into_iter()
+        .find(|condition| condition.type_ == "Running" && condition.status == "True")
+        .map(|condition| condition.reason)
+}
+
+/// Find the actor by its UID in all namespaces
+pub async fn find(client: &Client, uid: &str) -> Result<Option<Actor>> {
+    let api: Api<Actor> = Api::all(client.clone());
+    let params = ListParams::default().labels(&format!("{}={}", UID_LABEL_KEY, uid));
+    let actors = api.list(&params).await.map_err(Error::KubeError)?;
+
+    Ok(actors
+        .items
+        .into_iter()
+        .find(|actor| actor.uid().as_deref() == Some(uid)))
+}
+
+/// List the actors of playbook
+pub async fn list(client: &Client, playbook: &Playbook) -> Result<Vec<Actor>> {
+    let api: Api<Actor> = Api::namespaced(client.clone(), &playbook.spec.namespace);
+    let actors = api.list(&ListParams::default()).await.map_err(Error::KubeError)?;
+
+    Ok(actors.items)
+}
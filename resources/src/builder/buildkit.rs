@@ -0,0 +1,268 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::{Actor, ActorSpec};
+use async_trait::async_trait;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{
+    Container, EmptyDirVolumeSource, EnvVar, PodSpec, SeccompProfile, SecretVolumeSource, SecurityContext, Volume,
+    VolumeMount,
+};
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Client, ResourceExt};
+
+use super::{context_sub_path, docker_config_volume, secret_name, Builder, DOCKER_CONFIG_VOLUME};
+use crate::configuration::BuildKitConfiguration;
+use crate::error::{Error, Result};
+use crate::{job, settings};
+
+/// Where the build-time secrets are mounted in the build container.
+const BUILD_SECRETS_PATH: &str = "/run/secrets/amp";
+
+/// Build the image from Dockerfile with BuildKit, runs the rootless
+/// `buildkitd` in daemonless mode in a Job.
+pub struct BuildKit {
+    config: BuildKitConfiguration,
+    secrets: Vec<String>,
+}
+
+impl BuildKit {
+    pub fn new(config: BuildKitConfiguration, secrets: Vec<String>) -> Self {
+        Self { config, secrets }
+    }
+
+    /// Create a Job runs `buildctl-daemonless.sh`.
+    fn job(&self, actor: &Actor) -> Result<Job> {
+        let settings = settings::of(actor)?;
+        let spec = PodSpec {
+            containers: vec![container(
+                &actor.spec,
+                &self.config,
+                &settings.build.platforms,
+                &self.secrets,
+            )],
+            volumes: Some(vec![
+                docker_config_volume(),
+                Volume {
+                    name: "buildkitd".to_string(),
+                    empty_dir: Some(EmptyDirVolumeSource::default()),
+                    ..Default::default()
+                },
+                Volume {
+                    name: "secrets".to_string(),
+                    secret: Some(SecretVolumeSource {
+                        secret_name: Some(secret_name(actor)),
+                        optional: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ]),
+            node_selector: Some(self.config.node_selector.clone()).filter(|selector| !selector.is_empty()),
+            tolerations: Some(self.config.tolerations.clone()).filter(|tolerations| !tolerations.is_empty()),
+            ..Default::default()
+        };
+
+        job::new(actor, actor.spec.build_name(), spec)
+    }
+}
+
+#[async_trait]
+impl Builder for BuildKit {
+    async fn exists(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        job::exists(client, actor, &actor.spec.build_name()).await
+    }
+
+    async fn create(&self, client: &Client, actor: &Actor) -> Result<()> {
+        job::create(client, actor, self.job(actor)?).await.map(|_| ())
+    }
+
+    async fn update(&self, client: &Client, actor: &Actor) -> Result<()> {
+        job::update(client, actor, self.job(actor)?).await.map(|_| ())
+    }
+
+    async fn completed(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        job::completed(client, actor, &actor.spec.build_name()).await
+    }
+
+    async fn failed(&self, client: &Client, actor: &Actor) -> Result<Option<String>> {
+        job::failed(client, actor, &actor.spec.build_name()).await
+    }
+
+    async fn started(&self, client: &Client, actor: &Actor) -> Result<Option<DateTime<Utc>>> {
+        job::started(client, actor, &actor.spec.build_name()).await
+    }
+
+    async fn logs(&self, client: &Client, actor: &Actor) -> Result<String> {
+        let namespace = actor
+            .namespace()
+            .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+        let selector = format!("job-name={}", actor.spec.build_name());
+
+        super::logs(client, &namespace, &selector).await
+    }
+
+    async fn platforms(&self, client: &Client, actor: &Actor) -> Result<BTreeMap<String, String>> {
+        let platforms = settings::of(actor)?.build.platforms;
+        if platforms.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        // All platforms are built in the same Job, so they share its state.
+        let state = super::state(client, actor, &actor.spec.build_name()).await?;
+
+        Ok(platforms
+            .into_iter()
+            .map(|platform| (platform, state.clone()))
+            .collect())
+    }
+}
+
+/// The Git context of BuildKit, in form of `{repo}#{rev}:{sub-path}`.
+fn context(spec: &ActorSpec) -> String {
+    let mut context = format!("{}#{}", spec.source.repo, spec.source.rev());
+    if let Some(path) = context_sub_path(spec) {
+        context.push(':');
+        context.push_str(path);
+    }
+
+    context
+}
+
+fn container(spec: &ActorSpec, config: &BuildKitConfiguration, platforms: &[String], secrets: &[String]) -> Container {
+    let mut args: Vec<String> = vec![
+        "build".into(),
+        "--frontend=dockerfile.v0".into(),
+        format!("--opt=context={}", context(spec)),
+        format!("--output=type=image,name={},push=true", spec.docker_tag()),
+    ];
+
+    // Build a manifest list for the target platforms, the foreign
+    // architectures are emulated with QEMU (binfmt_misc) on nodes.
+    if !platforms.is_empty() {
+        args.push(format!("--opt=platform={}", platforms.join(",")));
+    }
+
+    // Export the cache to the cache repository if present,
+    // else, embed the cache into the pushed image.
+    match &config.cache_repo {
+        Some(repo) => {
+            let reference = format!("type=registry,ref={}:{}", repo, spec.build_name());
+            args.push(format!("--export-cache={},mode=max", reference));
+            args.push(format!("--import-cache={}", reference));
+        }
+        None => {
+            args.push("--export-cache=type=inline".into());
+            args.push(format!("--import-cache=type=registry,ref={}", spec.docker_tag()));
+        }
+    }
+
+    // Expose the secrets to `RUN --mount=type=secret,id=<key>`.
+    for id in secrets {
+        args.push(format!("--secret=id={},src={}/{}", id, BUILD_SECRETS_PATH, id));
+    }
+
+    if let Some(argments) = spec.build_args() {
+        args.extend(argments.iter().filter_map(|argment| translate(argment)));
+    }
+
+    let mut env = vec![
+        EnvVar {
+            name: "BUILDKITD_FLAGS".into(),
+            value: Some("--oci-worker-no-process-sandbox".into()),
+            ..Default::default()
+        },
+        EnvVar {
+            name: "DOCKER_CONFIG".into(),
+            value: Some("/home/user/.docker".into()),
+            ..Default::default()
+        },
+    ];
+    env.extend(spec.build_env().unwrap_or_default());
+
+    Container {
+        name: "build".to_string(),
+        image: Some(config.image.clone()),
+        image_pull_policy: Some(config.image_pull_policy.clone()),
+        command: Some(vec!["buildctl-daemonless.sh".into()]),
+        args: Some(args),
+        env: Some(env),
+        security_context: Some(SecurityContext {
+            run_as_user: Some(1000),
+            run_as_group: Some(1000),
+            seccomp_profile: Some(SeccompProfile {
+                type_: "Unconfined".into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        volume_mounts: Some(vec![
+            VolumeMount {
+                name: DOCKER_CONFIG_VOLUME.to_string(),
+                mount_path: "/home/user/.docker".to_string(),
+                ..Default::default()
+            },
+            VolumeMount {
+                name: "buildkitd".to_string(),
+                mount_path: "/home/user/.local/share/buildkit".to_string(),
+                ..Default::default()
+            },
+            VolumeMount {
+                name: "secrets".to_string(),
+                mount_path: BUILD_SECRETS_PATH.to_string(),
+                read_only: Some(true),
+                ..Default::default()
+            },
+        ]),
+        resources: config.resources.clone(),
+        ..Default::default()
+    }
+}
+
+/// Translate the build arguments of Kaniko into the options of BuildKit.
+fn translate(argment: &str) -> Option<String> {
+    if let Some(value) = argment.strip_prefix("--build-arg=") {
+        return Some(format!("--opt=build-arg:{}", value));
+    }
+    if let Some(value) = argment.strip_prefix("--dockerfile=") {
+        return Some(format!("--opt=filename={}", value));
+    }
+    if let Some(value) = argment.strip_prefix("--target=") {
+        return Some(format!("--opt=target={}", value));
+    }
+
+    tracing::debug!("Ignored the unsupported build argument of BuildKit: {}", argment);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate("--build-arg=VERSION=1.0"),
+            Some("--opt=build-arg:VERSION=1.0".into())
+        );
+        assert_eq!(
+            translate("--dockerfile=build/Dockerfile"),
+            Some("--opt=filename=build/Dockerfile".into())
+        );
+        assert_eq!(translate("--target=release"), Some("--opt=target=release".into()));
+        assert_eq!(translate("--snapshot-mode=redo"), None);
+    }
+}
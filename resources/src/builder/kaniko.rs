@@ -0,0 +1,434 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::{Actor, ActorSpec};
+use async_trait::async_trait;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{
+    Container, EnvVar, EnvVarSource, PersistentVolumeClaim, PersistentVolumeClaimSpec,
+    PersistentVolumeClaimVolumeSource, PodSpec, ResourceRequirements, SecretKeySelector, Volume, VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::core::ObjectMeta;
+use kube::{Client, ResourceExt};
+
+use super::{context_sub_path, docker_config_volume, secret_name, Builder, DOCKER_CONFIG_VOLUME};
+use crate::configuration::{KanikoConfiguration, WarmerConfiguration};
+use crate::error::{Error, Result};
+use crate::{job, persistent_volume_claim, settings};
+
+/// The volume of the cached base images, shared by builds in the namespace.
+const CACHE_VOLUME: &str = "amp-kaniko-cache";
+/// Where the cached base images are mounted.
+const CACHE_DIR: &str = "/cache";
+
+/// Build the image from Dockerfile with Kaniko, in a Job.
+///
+/// Kaniko can't build for foreign architectures, so if the target platforms
+/// are declared, there's one Job per platform runs on the nodes of that
+/// architecture, then the images are assembled into a manifest list.
+pub struct Kaniko {
+    config: KanikoConfiguration,
+    secrets: Vec<String>,
+}
+
+impl Kaniko {
+    pub fn new(config: KanikoConfiguration, secrets: Vec<String>) -> Self {
+        Self { config, secrets }
+    }
+
+    /// Create a Job runs the Kaniko executor, for the target platform if present.
+    fn job(&self, actor: &Actor, platform: Option<&str>) -> Result<Job> {
+        let mut node_selector = self.config.node_selector.clone();
+        if let Some(arch) = platform.and_then(|platform| platform.split('/').nth(1)) {
+            node_selector.insert("kubernetes.io/arch".into(), arch.into());
+        }
+
+        let mut volumes = vec![docker_config_volume()];
+        let mut init_containers = vec![];
+        if let Some(warmer) = &self.config.warmer {
+            volumes.push(Volume {
+                name: CACHE_VOLUME.to_string(),
+                persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                    claim_name: CACHE_VOLUME.to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            if !warmer.images.is_empty() {
+                init_containers.push(warmer_container(warmer));
+            }
+        }
+
+        let spec = PodSpec {
+            init_containers: Some(init_containers).filter(|containers| !containers.is_empty()),
+            containers: vec![container(actor, &self.config, platform, &self.secrets)],
+            volumes: Some(volumes),
+            node_selector: Some(node_selector).filter(|selector| !selector.is_empty()),
+            tolerations: Some(self.config.tolerations.clone()).filter(|tolerations| !tolerations.is_empty()),
+            ..Default::default()
+        };
+
+        job::new(actor, name(&actor.spec, platform), spec)
+    }
+
+    /// Create the cache volume of base images in the namespace of actor, if the warmer is enabled.
+    async fn warm(&self, client: &Client, actor: &Actor) -> Result<()> {
+        let warmer = match &self.config.warmer {
+            Some(warmer) => warmer,
+            None => return Ok(()),
+        };
+
+        let namespace = actor
+            .namespace()
+            .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+        if persistent_volume_claim::exists(client, &namespace, CACHE_VOLUME).await? {
+            return Ok(());
+        }
+
+        let resource = PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(CACHE_VOLUME.to_string()),
+                labels: Some(BTreeMap::from([(
+                    "app.kubernetes.io/managed-by".into(),
+                    "Amphitheatre".into(),
+                )])),
+                ..Default::default()
+            },
+            spec: Some(PersistentVolumeClaimSpec {
+                access_modes: Some(vec!["ReadWriteMany".into()]),
+                storage_class_name: warmer.storage_class.clone(),
+                resources: Some(ResourceRequirements {
+                    requests: Some(BTreeMap::from([("storage".into(), Quantity(warmer.size.clone()))])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        persistent_volume_claim::create(client, &namespace, &resource).await?;
+
+        Ok(())
+    }
+
+    /// Create a Job assembles the images of platforms into a manifest list.
+    fn manifest(&self, actor: &Actor, platforms: &[String]) -> Result<Job> {
+        let mut args = vec![
+            "index".to_string(),
+            "append".to_string(),
+            format!("--tag={}", actor.spec.docker_tag()),
+        ];
+        for platform in platforms {
+            args.push(format!("--manifest={}", destination(&actor.spec, Some(platform))));
+        }
+
+        let container = Container {
+            name: "manifest".to_string(),
+            image: Some(self.config.crane_image.clone()),
+            args: Some(args),
+            env: Some(vec![EnvVar {
+                name: "DOCKER_CONFIG".into(),
+                value: Some("/docker".into()),
+                ..Default::default()
+            }]),
+            volume_mounts: Some(vec![VolumeMount {
+                name: DOCKER_CONFIG_VOLUME.to_string(),
+                mount_path: "/docker".to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+
+        let spec = PodSpec {
+            containers: vec![container],
+            volumes: Some(vec![docker_config_volume()]),
+            ..Default::default()
+        };
+
+        job::new(actor, actor.spec.build_name(), spec)
+    }
+}
+
+#[async_trait]
+impl Builder for Kaniko {
+    async fn exists(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        let platforms = platforms(actor)?;
+        let platform = platforms.first().map(|platform| platform.as_str());
+
+        job::exists(client, actor, &name(&actor.spec, platform)).await
+    }
+
+    async fn create(&self, client: &Client, actor: &Actor) -> Result<()> {
+        self.warm(client, actor).await?;
+
+        let platforms = platforms(actor)?;
+        if platforms.is_empty() {
+            return job::create(client, actor, self.job(actor, None)?).await.map(|_| ());
+        }
+
+        for platform in &platforms {
+            job::create(client, actor, self.job(actor, Some(platform))?).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn update(&self, client: &Client, actor: &Actor) -> Result<()> {
+        self.warm(client, actor).await?;
+
+        let platforms = platforms(actor)?;
+        if platforms.is_empty() {
+            return job::update(client, actor, self.job(actor, None)?).await.map(|_| ());
+        }
+
+        let mut completed = true;
+        for platform in &platforms {
+            let name = name(&actor.spec, Some(platform));
+            match job::exists(client, actor, &name).await? {
+                true => job::update(client, actor, self.job(actor, Some(platform))?).await?,
+                false => job::create(client, actor, self.job(actor, Some(platform))?).await?,
+            };
+            completed &= job::completed(client, actor, &name).await?;
+        }
+
+        // Assemble the manifest list once the images of all platforms are pushed.
+        if completed {
+            let manifest = self.manifest(actor, &platforms)?;
+            match job::exists(client, actor, &actor.spec.build_name()).await? {
+                true => job::update(client, actor, manifest).await?,
+                false => job::create(client, actor, manifest).await?,
+            };
+        }
+
+        Ok(())
+    }
+
+    async fn completed(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        for platform in platforms(actor)? {
+            if !job::completed(client, actor, &name(&actor.spec, Some(&platform))).await? {
+                return Ok(false);
+            }
+        }
+
+        job::completed(client, actor, &actor.spec.build_name()).await
+    }
+
+    async fn failed(&self, client: &Client, actor: &Actor) -> Result<Option<String>> {
+        let mut completed = true;
+        for platform in platforms(actor)? {
+            let name = name(&actor.spec, Some(&platform));
+            if let Some(reason) = job::failed(client, actor, &name).await? {
+                return Ok(Some(reason));
+            }
+            completed &= job::completed(client, actor, &name).await?;
+        }
+
+        // The manifest Job is refreshed only if the images of all platforms are pushed.
+        match completed {
+            true => job::failed(client, actor, &actor.spec.build_name()).await,
+            false => Ok(None),
+        }
+    }
+
+    async fn started(&self, client: &Client, actor: &Actor) -> Result<Option<DateTime<Utc>>> {
+        // The Jobs of all platforms are created at once, the first one starts the build.
+        let platforms = platforms(actor)?;
+        let platform = platforms.first().map(|platform| platform.as_str());
+
+        job::started(client, actor, &name(&actor.spec, platform)).await
+    }
+
+    async fn logs(&self, client: &Client, actor: &Actor) -> Result<String> {
+        let namespace = actor
+            .namespace()
+            .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+
+        // The logs of the Jobs for each platform, followed by the manifest Job.
+        let mut names: Vec<String> = platforms(actor)?
+            .iter()
+            .map(|platform| name(&actor.spec, Some(platform)))
+            .collect();
+        names.push(actor.spec.build_name());
+
+        let mut content = String::new();
+        for name in names {
+            content.push_str(&super::logs(client, &namespace, &format!("job-name={}", name)).await?);
+        }
+
+        Ok(content)
+    }
+
+    async fn platforms(&self, client: &Client, actor: &Actor) -> Result<BTreeMap<String, String>> {
+        let mut states = BTreeMap::new();
+        for platform in platforms(actor)? {
+            let state = super::state(client, actor, &name(&actor.spec, Some(&platform))).await?;
+            states.insert(platform, state);
+        }
+
+        Ok(states)
+    }
+}
+
+#[inline]
+fn platforms(actor: &Actor) -> Result<Vec<String>> {
+    Ok(settings::of(actor)?.build.platforms)
+}
+
+/// The suffix of the platform, e.g. `linux/arm64/v8` => `arm64-v8`.
+#[inline]
+fn suffix(platform: &str) -> String {
+    platform.split('/').skip(1).collect::<Vec<_>>().join("-")
+}
+
+/// The name of Job builds the image for the target platform.
+fn name(spec: &ActorSpec, platform: Option<&str>) -> String {
+    match platform {
+        Some(platform) => format!("{}-{}", spec.build_name(), suffix(platform)),
+        None => spec.build_name(),
+    }
+}
+
+/// The image is tagged with the platform suffix, if it's a part of manifest list.
+fn destination(spec: &ActorSpec, platform: Option<&str>) -> String {
+    match platform {
+        Some(platform) => platform_tag(&spec.docker_tag(), platform),
+        None => spec.docker_tag(),
+    }
+}
+
+/// The tag of the image for the target platform, e.g. `v1` => `v1-arm64-v8`,
+/// it's referenced by the manifest list tagged with `tag`.
+pub fn platform_tag(tag: &str, platform: &str) -> String {
+    format!("{}-{}", tag, suffix(platform))
+}
+
+#[inline]
+fn context(spec: &ActorSpec) -> String {
+    format!("{}#{}", spec.source.repo.replace("https", "git"), spec.source.rev())
+}
+
+fn container(actor: &Actor, config: &KanikoConfiguration, platform: Option<&str>, secrets: &[String]) -> Container {
+    let spec = &actor.spec;
+    let mut args: Vec<String> = vec![("context", context(spec)), ("destination", destination(spec, platform))]
+        .iter()
+        .map(|(key, value)| format!("--{}={}", key, value))
+        .collect();
+    args.extend(config.args());
+
+    if let Some(platform) = platform {
+        args.push(format!("--custom-platform={}", platform));
+    }
+
+    let mut volume_mounts = vec![VolumeMount {
+        name: DOCKER_CONFIG_VOLUME.to_string(),
+        mount_path: "/kaniko/.docker".to_string(),
+        ..Default::default()
+    }];
+    if config.warmer.is_some() {
+        args.push(format!("--cache-dir={}", CACHE_DIR));
+        volume_mounts.push(VolumeMount {
+            name: CACHE_VOLUME.to_string(),
+            mount_path: CACHE_DIR.to_string(),
+            ..Default::default()
+        });
+    }
+
+    // The sub-path of the character in a monorepo
+    if let Some(path) = context_sub_path(spec) {
+        args.push(format!("--context-sub-path={}", path));
+    }
+
+    if let Some(argments) = spec.build_args() {
+        args.extend(argments);
+    }
+
+    // The build-time secrets are passed as the build args without values,
+    // Kaniko takes the values from the environment, read from the Secret.
+    let mut env = spec.build_env().unwrap_or_default();
+    for key in secrets {
+        args.push(format!("--build-arg={}", key));
+        env.push(EnvVar {
+            name: key.clone(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(secret_name(actor)),
+                    key: key.clone(),
+                    optional: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
+    Container {
+        name: "build".to_string(),
+        image: Some(config.image.clone()),
+        image_pull_policy: Some(config.image_pull_policy.clone()),
+        args: Some(args),
+        env: Some(env).filter(|env| !env.is_empty()),
+        volume_mounts: Some(volume_mounts),
+        resources: config.resources.clone(),
+        ..Default::default()
+    }
+}
+
+/// The container caches the base images before the build, the cached ones are skipped.
+fn warmer_container(warmer: &WarmerConfiguration) -> Container {
+    let mut args = vec![format!("--cache-dir={}", CACHE_DIR)];
+    args.extend(warmer.images.iter().map(|image| format!("--image={}", image)));
+
+    Container {
+        name: "warmer".to_string(),
+        image: Some(warmer.image.clone()),
+        args: Some(args),
+        volume_mounts: Some(vec![
+            VolumeMount {
+                name: DOCKER_CONFIG_VOLUME.to_string(),
+                mount_path: "/kaniko/.docker".to_string(),
+                ..Default::default()
+            },
+            VolumeMount {
+                name: CACHE_VOLUME.to_string(),
+                mount_path: CACHE_DIR.to_string(),
+                ..Default::default()
+            },
+        ]),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suffix() {
+        assert_eq!(suffix("linux/amd64"), "amd64");
+        assert_eq!(suffix("linux/arm64/v8"), "arm64-v8");
+        assert_eq!(suffix("linux"), "");
+    }
+
+    #[test]
+    fn test_platform_tag() {
+        assert_eq!(platform_tag("v1", "linux/amd64"), "v1-amd64");
+        assert_eq!(
+            platform_tag("registry.local/app:v1", "linux/arm64/v8"),
+            "registry.local/app:v1-arm64-v8"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::schema::Actor;
+use async_trait::async_trait;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Client, ResourceExt};
+
+use super::{secret_name, Builder};
+use crate::error::{Error, Result};
+use crate::{image, settings};
+
+/// Build the image from source code with Cloud Native Buildpacks, by kpack.
+pub struct Kpack {
+    secrets: Vec<String>,
+}
+
+impl Kpack {
+    pub fn new(secrets: Vec<String>) -> Self {
+        Self { secrets }
+    }
+
+    /// The build-time secrets are bound to the builds as a service binding.
+    fn binding(&self, actor: &Actor) -> Option<String> {
+        match self.secrets.is_empty() {
+            true => None,
+            false => Some(secret_name(actor)),
+        }
+    }
+}
+
+#[async_trait]
+impl Builder for Kpack {
+    async fn exists(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        image::exists(client, actor).await
+    }
+
+    fn unsupported(&self, actor: &Actor) -> Result<Option<String>> {
+        // The builders of kpack build for the architecture of nodes only.
+        match settings::of(actor)?.build.platforms.is_empty() {
+            true => Ok(None),
+            false => Ok(Some("UnsupportedPlatforms".into())),
+        }
+    }
+
+    async fn create(&self, client: &Client, actor: &Actor) -> Result<()> {
+        image::create(client, actor, self.binding(actor)).await.map(|_| ())
+    }
+
+    async fn update(&self, client: &Client, actor: &Actor) -> Result<()> {
+        image::update(client, actor, self.binding(actor)).await.map(|_| ())
+    }
+
+    async fn completed(&self, client: &Client, actor: &Actor) -> Result<bool> {
+        image::completed(client, actor).await
+    }
+
+    async fn failed(&self, client: &Client, actor: &Actor) -> Result<Option<String>> {
+        image::failed(client, actor).await
+    }
+
+    async fn started(&self, client: &Client, actor: &Actor) -> Result<Option<DateTime<Utc>>> {
+        image::started(client, actor).await
+    }
+
+    async fn logs(&self, client: &Client, actor: &Actor) -> Result<String> {
+        let namespace = actor
+            .namespace()
+            .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+        // The build pods of kpack are labeled with the name of Image.
+        let selector = format!("image.kpack.io/image={}", actor.spec.build_name());
+
+        super::logs(client, &namespace, &selector).await
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use amp_common::schema::{Actor, ActorSpec};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{KeyToPath, Pod, Secret, SecretVolumeSource, Volume};
+use k8s_openapi::chrono::{DateTime, Utc};
+use k8s_openapi::ByteString;
+use kube::api::{ListParams, LogParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde::{Deserialize, Serialize};
+
+use super::configuration::Configuration;
+use super::error::{Error, Result};
+use super::{job, secret, settings, sub_path};
+
+pub mod buildkit;
+pub mod kaniko;
+pub mod kpack;
+
+/// The states of the build for each target platform of actor (in JSON).
+pub const PLATFORMS_ANNOTATION_KEY: &str = "amphitheatre.app/build-platforms";
+
+/// The Secret holds the values of build-time secrets, in the namespace of Amphitheatre.
+pub const BUILD_SECRETS_NAME: &str = "amp-build-secrets";
+
+/// The bounds of the interval to check if the in-flight build has timed out.
+const MIN_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+const MAX_RECHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The volume of the registry credentials for pushing images.
+const DOCKER_CONFIG_VOLUME: &str = "docker-config";
+
+/// The backends for building images of actors.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Build the image from Dockerfile with Kaniko.
+    Kaniko,
+    /// Build the image from Dockerfile with BuildKit.
+    BuildKit,
+    /// Build the image from source code with Cloud Native Buildpacks.
+    Kpack,
+}
+
+/// The build backend of actor, it builds the image of actor and
+/// reports the progress of the build.
+#[async_trait]
+pub trait Builder: Send + Sync {
+    /// Check if the build resource of actor exists.
+    async fn exists(&self, client: &Client, actor: &Actor) -> Result<bool>;
+
+    /// Check if the build of actor can't be handled by this backend, returns
+    /// the reason, it's checked before the build resource is created.
+    fn unsupported(&self, _actor: &Actor) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Create the build resource of actor.
+    async fn create(&self, client: &Client, actor: &Actor) -> Result<()>;
+
+    /// Update the build resource of actor if there are new changes.
+    async fn update(&self, client: &Client, actor: &Actor) -> Result<()>;
+
+    /// Check if the build has completed successfully.
+    async fn completed(&self, client: &Client, actor: &Actor) -> Result<bool>;
+
+    /// Check if the build has failed or timed out, returns the reason.
+    async fn failed(&self, client: &Client, actor: &Actor) -> Result<Option<String>>;
+
+    /// When the latest build was started, none if it's not found.
+    async fn started(&self, client: &Client, actor: &Actor) -> Result<Option<DateTime<Utc>>>;
+
+    /// Read the logs of the latest build.
+    async fn logs(&self, client: &Client, actor: &Actor) -> Result<String>;
+
+    /// The states of the build for each target platform, if the backend
+    /// builds the platforms separately.
+    async fn platforms(&self, _client: &Client, _actor: &Actor) -> Result<BTreeMap<String, String>> {
+        Ok(BTreeMap::new())
+    }
+}
+
+/// Choose the builder for actor, the backend declared in the manifest
+/// takes precedence over the one in the platform configuration. If neither
+/// is present, prefer to use Kaniko to build images with Dockerfile, else,
+/// build the image with Cloud Native Buildpacks.
+pub fn new(actor: &Actor, configuration: &Configuration) -> Result<Box<dyn Builder>> {
+    let settings = settings::of(actor)?;
+    let backend = settings
+        .build
+        .backend
+        .or(configuration.build.backend)
+        .unwrap_or(match actor.spec.has_dockerfile() {
+            true => Backend::Kaniko,
+            false => Backend::Kpack,
+        });
+    tracing::debug!("Build the image of actor {} with {:?}", actor.name_any(), backend);
+
+    // The cache repository of actor takes precedence over the platform default.
+    let cache_repo = settings.build.cache_repo.clone();
+    let secrets = keys(actor, configuration)?;
+
+    let builder: Box<dyn Builder> = match backend {
+        Backend::Kaniko => {
+            let mut config = configuration.kaniko.clone();
+            config.cache_repo = cache_repo.or(config.cache_repo);
+            Box::new(kaniko::Kaniko::new(config, secrets))
+        }
+        Backend::BuildKit => {
+            let mut config = configuration.buildkit.clone();
+            config.cache_repo = cache_repo.or(config.cache_repo);
+            Box::new(buildkit::BuildKit::new(config, secrets))
+        }
+        Backend::Kpack => Box::new(kpack::Kpack::new(secrets)),
+    };
+
+    Ok(builder)
+}
+
+/// How long to wait before checking if the in-flight build has timed out, it's
+/// the time remaining until its deadline, capped so that it's checked regularly.
+pub fn recheck(started: Option<DateTime<Utc>>, timeout: Duration, now: DateTime<Utc>) -> Duration {
+    let remaining = match started {
+        Some(started) => timeout.saturating_sub((now - started).to_std().unwrap_or_default()),
+        None => MAX_RECHECK_INTERVAL,
+    };
+
+    remaining.clamp(MIN_RECHECK_INTERVAL, MAX_RECHECK_INTERVAL)
+}
+
+/// The keys of build-time secrets exposed to the build of actor.
+pub fn keys(actor: &Actor, configuration: &Configuration) -> Result<Vec<String>> {
+    let mut keys = configuration.build.secrets.clone();
+    for key in settings::of(actor)?.build.secrets {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    Ok(keys)
+}
+
+/// The name of Secret holds the build-time secrets of actor, in its namespace.
+pub fn secret_name(actor: &Actor) -> String {
+    format!("{}-secrets", actor.spec.build_name())
+}
+
+/// Materialize the build-time secrets of actor from the source Secret, only
+/// the keys exposed to the build are copied, returns the missing keys.
+pub async fn materialize(
+    client: &Client,
+    actor: &Actor,
+    source: Option<Secret>,
+    keys: &[String],
+) -> Result<Vec<String>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let mut values = source.and_then(|secret| secret.data).unwrap_or_default();
+
+    let mut data = BTreeMap::new();
+    let mut missing = vec![];
+    for key in keys {
+        match values.remove(key) {
+            Some(value) => {
+                data.insert(key.clone(), value);
+            }
+            None => missing.push(key.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        return Ok(missing);
+    }
+
+    // The type of service binding, required by kpack.
+    data.insert("type".into(), ByteString(b"amp-build-secrets".to_vec()));
+
+    let resource = Secret {
+        metadata: ObjectMeta {
+            name: Some(secret_name(actor)),
+            owner_references: Some(vec![actor.controller_owner_ref(&()).unwrap()]),
+            ..Default::default()
+        },
+        type_: Some("Opaque".into()),
+        data: Some(data),
+        ..Default::default()
+    };
+    secret::create(client, &namespace, resource).await?;
+
+    Ok(missing)
+}
+
+/// The sub-path of the character in a monorepo.
+#[inline]
+fn context_sub_path(spec: &ActorSpec) -> Option<&str> {
+    sub_path(spec.source.path.as_deref())
+}
+
+/// The registry credentials synchronized into the namespace of actor,
+/// mounted as the `config.json` of Docker.
+fn docker_config_volume() -> Volume {
+    Volume {
+        name: DOCKER_CONFIG_VOLUME.to_string(),
+        secret: Some(SecretVolumeSource {
+            secret_name: Some("amp-registry-credentials".to_string()),
+            items: Some(vec![KeyToPath {
+                key: ".dockerconfigjson".to_string(),
+                path: "config.json".to_string(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// The state of the build Job, in the form of the platform states.
+async fn state(client: &Client, actor: &Actor, name: &str) -> Result<String> {
+    let state = if job::failed(client, actor, name).await?.is_some() {
+        "Failed"
+    } else if job::completed(client, actor, name).await? {
+        "Succeeded"
+    } else {
+        "Building"
+    };
+
+    Ok(state.to_string())
+}
+
+/// Read the logs of all containers in the latest pod matched the label selector.
+async fn logs(client: &Client, namespace: &str, selector: &str) -> Result<String> {
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    let pods = api
+        .list(&ListParams::default().labels(selector))
+        .await
+        .map_err(Error::KubeError)?;
+
+    let pod = match pods.items.into_iter().max_by_key(|pod| pod.creation_timestamp()) {
+        Some(pod) => pod,
+        None => return Ok(String::new()),
+    };
+
+    let spec = pod.spec.clone().unwrap_or_default();
+    let containers = spec
+        .init_containers
+        .unwrap_or_default()
+        .into_iter()
+        .chain(spec.containers.into_iter());
+
+    let mut content = String::new();
+    for container in containers {
+        let params = LogParams {
+            container: Some(container.name),
+            ..Default::default()
+        };
+        // The container may not have started yet, skip it.
+        if let Ok(logs) = api.logs(&pod.name_any(), &params).await {
+            content.push_str(&logs);
+        }
+    }
+
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::Duration as Elapsed;
+
+    use super::*;
+
+    #[test]
+    fn test_recheck() {
+        let now = Utc::now();
+        let timeout = Duration::from_secs(3600);
+
+        assert_eq!(recheck(None, timeout, now), MAX_RECHECK_INTERVAL);
+        assert_eq!(recheck(Some(now), timeout, now), MAX_RECHECK_INTERVAL);
+        assert_eq!(
+            recheck(Some(now - Elapsed::seconds(3540)), timeout, now),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            recheck(Some(now - Elapsed::seconds(7200)), timeout, now),
+            MIN_RECHECK_INTERVAL
+        );
+        assert_eq!(
+            recheck(Some(now + Elapsed::seconds(60)), timeout, now),
+            MAX_RECHECK_INTERVAL
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+
+use super::error::{Error, Result};
+use super::settings::{self, ConfigSettings};
+use super::{hash, pod, LAST_APPLIED_HASH_KEY};
+
+/// The label of ConfigMaps which carries the name of the declared config.
+pub const CONFIG_LABEL_KEY: &str = "amphitheatre.app/config";
+
+/// The hashes of the contents of configs keyed by their names (in JSON), they're
+/// annotated on the pods, so that the pods are rolled when the files changed.
+pub const HASHES_ANNOTATION_KEY: &str = "amphitheatre.app/config-hashes";
+
+/// The hash of where the files of ConfigMap are fetched from.
+const SOURCE_HASH_KEY: &str = "amphitheatre.app/source-hash";
+
+/// The name of ConfigMap for the config of the actor.
+pub fn name(actor: &Actor, config: &ConfigSettings) -> String {
+    format!("{}-{}", actor.name_any(), config.name)
+}
+
+/// The content hash of the ConfigMap of config, if it's applied from the
+/// files at the current revision of actor, so they're not fetched again.
+pub async fn applied(client: &Client, actor: &Actor, config: &ConfigSettings) -> Result<Option<String>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let config_map = match api.get_opt(&name(actor, config)).await.map_err(Error::KubeError)? {
+        Some(config_map) => config_map,
+        None => return Ok(None),
+    };
+    if config_map.annotations().get(SOURCE_HASH_KEY) != Some(&source_hash(actor, config)?) {
+        return Ok(None);
+    }
+
+    Ok(config_map.annotations().get(LAST_APPLIED_HASH_KEY).cloned())
+}
+
+/// Apply the ConfigMap of the config with the contents of its files keyed by
+/// their base names, returns the hash of the contents.
+pub async fn apply(
+    client: &Client,
+    actor: &Actor,
+    config: &ConfigSettings,
+    data: BTreeMap<String, String>,
+) -> Result<String> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = name(actor, config);
+    let content_hash = hash(&data)?;
+
+    let resource = new(actor, config, data, &content_hash)?;
+    tracing::debug!("The applying ConfigMap resource:\n {:?}\n", resource);
+
+    api.patch(
+        &name,
+        &PatchParams::apply("amp-controllers").force(),
+        &Patch::Apply(&resource),
+    )
+    .await
+    .map_err(Error::KubeError)?;
+
+    tracing::info!("Applied ConfigMap: {}", name);
+    Ok(content_hash)
+}
+
+/// Delete the ConfigMaps of the configs which are no longer declared by actor.
+pub async fn prune(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace.as_str());
+    let configs = settings::of(actor)?.deploy.configs;
+
+    let params = ListParams::default().labels(&format!(
+        "app.kubernetes.io/name={},{}",
+        actor.name_any(),
+        CONFIG_LABEL_KEY
+    ));
+    for config_map in api.list(&params).await.map_err(Error::KubeError)? {
+        let declared = config_map
+            .labels()
+            .get(CONFIG_LABEL_KEY)
+            .map_or(false, |name| configs.iter().any(|config| &config.name == name));
+        if !declared {
+            api.delete(&config_map.name_any(), &DeleteParams::default())
+                .await
+                .map_err(Error::KubeError)?;
+            tracing::info!("Deleted ConfigMap: {}", config_map.name_any());
+        }
+    }
+
+    Ok(())
+}
+
+/// The hash of the source (at the revision) of actor and the declared files.
+#[inline]
+fn source_hash(actor: &Actor, config: &ConfigSettings) -> Result<String> {
+    hash(&(&actor.spec.source, &config.files))
+}
+
+fn new(
+    actor: &Actor,
+    config: &ConfigSettings,
+    data: BTreeMap<String, String>,
+    content_hash: &str,
+) -> Result<ConfigMap> {
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let mut labels = pod::labels(actor);
+    labels.insert(CONFIG_LABEL_KEY.into(), config.name.clone());
+    let annotations = BTreeMap::from([
+        (LAST_APPLIED_HASH_KEY.into(), content_hash.into()),
+        (SOURCE_HASH_KEY.into(), source_hash(actor, config)?),
+    ]);
+
+    let resource = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name(actor, config)),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels),
+            annotations: Some(annotations),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    Ok(resource)
+}
@@ -0,0 +1,282 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{ConfigMap, ResourceRequirements, Toleration};
+use kube::{Api, Client};
+use serde::{Deserialize, Serialize};
+
+use super::builder::Backend;
+use super::error::{Error, Result};
+use super::{DEFAULT_BUILDKIT_IMAGE, DEFAULT_CRANE_IMAGE, DEFAULT_KANIKO_IMAGE, DEFAULT_WARMER_IMAGE};
+
+/// The name of the ConfigMap holds the platform configuration.
+pub const CONFIGMAP_NAME: &str = "amp-configurations";
+/// The key of the configuration file in ConfigMap.
+pub const CONFIGURATION_KEY: &str = "configuration.toml";
+
+/// The platform configuration besides the credentials, they are read
+/// from the same `configuration.toml`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Configuration {
+    pub webhooks: WebhookConfiguration,
+    pub build: BuildConfiguration,
+    pub kaniko: KanikoConfiguration,
+    pub buildkit: BuildKitConfiguration,
+    /// The public endpoints of actors are exposed with Ingress, if it's present.
+    pub ingress: Option<IngressConfiguration>,
+    /// The names of Secrets in the namespace of Amphitheatre, they're copied into
+    /// the namespaces of playbooks, so that the actors can reference them.
+    pub secrets: Vec<String>,
+}
+
+/// The Ingress for the exposed ports of actors, their hosts are
+/// `{host}.{playbook}.{domain}`, the `host` is the actor name by default.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct IngressConfiguration {
+    /// The base domain of hosts, e.g. `preview.example.com`.
+    pub domain: String,
+    /// The class of Ingress, the default one of cluster is used if absent.
+    pub class_name: Option<String>,
+    /// The cert-manager ClusterIssuer for the certificates of hosts,
+    /// the endpoints are served over plain HTTP if it's absent.
+    pub issuer: Option<String>,
+    /// The additional annotations of Ingress, e.g. for the ingress controller.
+    pub annotations: BTreeMap<String, String>,
+    /// The namespace of the ingress controller, the exposed ports of actors accept
+    /// the traffic from it. The traffic is denied by the NetworkPolicies if it's
+    /// absent, so it's required when the playbooks are isolated.
+    pub namespace: Option<String>,
+}
+
+/// The secrets for verifying the webhooks from Git providers.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhookConfiguration {
+    /// The secret of GitHub webhooks, signed with `X-Hub-Signature-256`.
+    pub github: Option<String>,
+    /// The secret token of GitLab webhooks, sent with `X-Gitlab-Token`.
+    pub gitlab: Option<String>,
+    /// The secret of Gitea webhooks, signed with `X-Gitea-Signature`.
+    pub gitea: Option<String>,
+}
+
+/// The platform defaults of builds.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BuildConfiguration {
+    /// The default backend for building images, if it's not declared by actor.
+    pub backend: Option<Backend>,
+    /// The number of the most recent tags to keep in the image and cache
+    /// repositories of actors, the pruning is disabled if it's absent.
+    pub retention: Option<usize>,
+    /// The keys of Secret `amp-build-secrets` in the namespace of Amphitheatre,
+    /// they're exposed to all builds besides the ones declared by actors.
+    pub secrets: Vec<String>,
+}
+
+/// The options of the Kaniko executor for building images.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KanikoConfiguration {
+    /// The image of Kaniko executor.
+    pub image: String,
+    /// The pull policy of the executor image, e.g. `Always`, `IfNotPresent`.
+    pub image_pull_policy: String,
+    /// The log level: `panic`, `fatal`, `error`, `warn`, `info`, `debug` or `trace`.
+    pub verbosity: String,
+    /// Whether to cache the layers of builds.
+    pub cache: bool,
+    /// The repository for storing cached layers, defaults to `{destination}/cache`.
+    pub cache_repo: Option<String>,
+    /// The snapshot mode: `full`, `redo` or `time`.
+    pub snapshot_mode: Option<String>,
+    /// The mirrors of registries used to pull the base images.
+    pub registry_mirrors: Vec<String>,
+    /// The registries allowed to push to and pull from over plain HTTP.
+    pub insecure_registries: Vec<String>,
+    /// The compute resources (CPU & Memory) of the executor container.
+    pub resources: Option<ResourceRequirements>,
+    /// The node selector of the build pods.
+    pub node_selector: BTreeMap<String, String>,
+    /// The tolerations of the build pods.
+    pub tolerations: Vec<Toleration>,
+    /// The image of crane, assembles the images of platforms into a manifest list.
+    pub crane_image: String,
+    /// Warm the cache of base images before builds, if it's present.
+    pub warmer: Option<WarmerConfiguration>,
+}
+
+impl Default for KanikoConfiguration {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_KANIKO_IMAGE.into(),
+            image_pull_policy: "Always".into(),
+            verbosity: "trace".into(),
+            cache: true,
+            cache_repo: None,
+            snapshot_mode: None,
+            registry_mirrors: vec![],
+            insecure_registries: vec![],
+            resources: None,
+            node_selector: BTreeMap::new(),
+            tolerations: vec![],
+            crane_image: DEFAULT_CRANE_IMAGE.into(),
+            warmer: None,
+        }
+    }
+}
+
+impl KanikoConfiguration {
+    /// The arguments of the Kaniko executor derived from this configuration.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![
+            format!("--verbosity={}", self.verbosity),
+            format!("--cache={}", self.cache),
+        ];
+
+        if let Some(repo) = &self.cache_repo {
+            args.push(format!("--cache-repo={}", repo));
+        }
+        if let Some(mode) = &self.snapshot_mode {
+            args.push(format!("--snapshot-mode={}", mode));
+        }
+        for mirror in &self.registry_mirrors {
+            args.push(format!("--registry-mirror={}", mirror));
+        }
+        for registry in &self.insecure_registries {
+            args.push(format!("--insecure-registry={}", registry));
+        }
+
+        args
+    }
+}
+
+/// The options of the Kaniko warmer, it caches the base images into a
+/// volume shared by the builds in the same namespace.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WarmerConfiguration {
+    /// The image of Kaniko warmer.
+    pub image: String,
+    /// The base images to be cached, e.g. `golang:1.20`.
+    pub images: Vec<String>,
+    /// The storage class of the cache volume, it must support `ReadWriteMany`.
+    pub storage_class: Option<String>,
+    /// The size of the cache volume.
+    pub size: String,
+}
+
+impl Default for WarmerConfiguration {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_WARMER_IMAGE.into(),
+            images: vec![],
+            storage_class: None,
+            size: "10Gi".into(),
+        }
+    }
+}
+
+/// The options of the BuildKit backend for building images.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BuildKitConfiguration {
+    /// The image of rootless BuildKit.
+    pub image: String,
+    /// The pull policy of the BuildKit image, e.g. `Always`, `IfNotPresent`.
+    pub image_pull_policy: String,
+    /// The repository for importing and exporting the build cache, the cache
+    /// is inlined into the pushed image if it's absent.
+    pub cache_repo: Option<String>,
+    /// The compute resources (CPU & Memory) of the build container.
+    pub resources: Option<ResourceRequirements>,
+    /// The node selector of the build pods.
+    pub node_selector: BTreeMap<String, String>,
+    /// The tolerations of the build pods.
+    pub tolerations: Vec<Toleration>,
+}
+
+impl Default for BuildKitConfiguration {
+    fn default() -> Self {
+        Self {
+            image: DEFAULT_BUILDKIT_IMAGE.into(),
+            image_pull_policy: "IfNotPresent".into(),
+            cache_repo: None,
+            resources: None,
+            node_selector: BTreeMap::new(),
+            tolerations: vec![],
+        }
+    }
+}
+
+/// Parse the configuration from the content of `configuration.toml`.
+pub fn parse(content: &str) -> Result<Configuration> {
+    toml::from_str(content).map_err(Error::TomlParseError)
+}
+
+/// Load the configuration from the ConfigMap in the namespace of Amphitheatre,
+/// returns the default configuration if it's absent.
+pub async fn load(client: &Client, namespace: &str) -> Result<Configuration> {
+    let api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let config_map = api.get_opt(CONFIGMAP_NAME).await.map_err(Error::KubeError)?;
+    let content = config_map
+        .and_then(|cm| cm.data)
+        .and_then(|mut data| data.remove(CONFIGURATION_KEY));
+
+    match content {
+        Some(content) => parse(&content),
+        None => Ok(Configuration::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kaniko_default_args() {
+        let args = KanikoConfiguration::default().args();
+        assert_eq!(args, vec!["--verbosity=trace", "--cache=true"]);
+    }
+
+    #[test]
+    fn test_kaniko_args() {
+        let kaniko = KanikoConfiguration {
+            verbosity: "info".into(),
+            cache: false,
+            cache_repo: Some("registry.local/cache".into()),
+            snapshot_mode: Some("redo".into()),
+            registry_mirrors: vec!["mirror.gcr.io".into()],
+            insecure_registries: vec!["registry.local".into(), "registry.test".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            kaniko.args(),
+            vec![
+                "--verbosity=info",
+                "--cache=false",
+                "--cache-repo=registry.local/cache",
+                "--snapshot-mode=redo",
+                "--registry-mirror=mirror.gcr.io",
+                "--insecure-registry=registry.local",
+                "--insecure-registry=registry.test",
+            ]
+        );
+    }
+}
@@ -15,15 +15,15 @@
 use std::collections::BTreeMap;
 
 use amp_common::schema::Actor;
-use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
-use k8s_openapi::api::core::v1::{Container, PodSpec, PodTemplateSpec};
+use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy};
+use k8s_openapi::api::core::v1::{PersistentVolumeClaimVolumeSource, Volume};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
-use kube::api::{Patch, PatchParams, PostParams};
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams};
 use kube::core::ObjectMeta;
 use kube::{Api, Client, Resource, ResourceExt};
 
 use super::error::{Error, Result};
-use super::{hash, LAST_APPLIED_HASH_KEY};
+use super::{persistent_volume_claim, pod, settings, LAST_APPLIED_HASH_KEY};
 
 pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     let namespace = actor
@@ -63,7 +63,7 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<Deployment> {
     let mut deployment = api.get(&name).await.map_err(Error::KubeError)?;
     tracing::debug!("The Deployment {} already exists: {:?}", &name, deployment);
 
-    let expected_hash = hash(&actor.spec)?;
+    let expected_hash = pod::fingerprint(actor)?;
     let found_hash: String = deployment
         .annotations()
         .get(LAST_APPLIED_HASH_KEY)
@@ -92,31 +92,32 @@ fn new(actor: &Actor) -> Result<Deployment> {
     let name = actor.name_any();
 
     let owner_reference = actor.controller_owner_ref(&()).unwrap();
-    let labels = BTreeMap::from([
-        ("app.kubernetes.io/name".into(), name.clone()),
-        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
-    ]);
-    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&actor.spec)?)]);
-
-    let container = Container {
-        name: name.clone(),
-        image: Some(actor.spec.docker_tag()),
-        image_pull_policy: Some("Always".into()),
-        env: actor.spec.environments(),
-        ports: actor.spec.container_ports(),
-        ..Default::default()
-    };
-
-    let template = PodTemplateSpec {
-        metadata: Some(ObjectMeta {
-            labels: Some(labels.clone()),
+    let labels = pod::labels(actor);
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), pod::fingerprint(actor)?)]);
+
+    // The volumes are claimed by the PVCs of actor, and the old pods must be
+    // killed before the new ones are created, since they can't share the volumes.
+    let volumes = settings::of(actor)?.deploy.volumes;
+    let mut template = pod::template(actor)?;
+    let mut strategy = None;
+    if !volumes.is_empty() {
+        if let Some(spec) = template.spec.as_mut() {
+            spec.volumes
+                .get_or_insert_with(Vec::new)
+                .extend(volumes.iter().map(|volume| Volume {
+                    name: volume.name.clone(),
+                    persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                        claim_name: persistent_volume_claim::name(actor, volume),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }));
+        }
+        strategy = Some(DeploymentStrategy {
+            type_: Some("Recreate".into()),
             ..Default::default()
-        }),
-        spec: Some(PodSpec {
-            containers: vec![container],
-            ..Default::default()
-        }),
-    };
+        });
+    }
 
     let resource = Deployment {
         metadata: ObjectMeta {
@@ -131,10 +132,51 @@ fn new(actor: &Actor) -> Result<Deployment> {
                 match_labels: Some(labels),
                 ..Default::default()
             },
+            replicas: pod::replicas(actor)?,
             template,
+            strategy,
             ..Default::default()
         }),
         ..Default::default()
     };
     Ok(resource)
 }
+
+/// Delete the Deployment of actor, if it exists.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Deployment> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted Deployment: {}", name);
+    }
+
+    Ok(())
+}
+
+/// Whether the rollout of the Deployment has completed, all the replicas
+/// are updated to the latest revision and available.
+pub async fn ready(client: &Client, actor: &Actor) -> Result<bool> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Deployment> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let deployment = match api.get_opt(&actor.name_any()).await.map_err(Error::KubeError)? {
+        Some(deployment) => deployment,
+        None => return Ok(false),
+    };
+    let replicas = deployment.spec.as_ref().and_then(|spec| spec.replicas).unwrap_or(1);
+    let status = deployment.status.unwrap_or_default();
+
+    Ok(status.observed_generation >= deployment.metadata.generation
+        && status.updated_replicas.unwrap_or_default() >= replicas
+        && status.available_replicas.unwrap_or_default() >= replicas
+        && status.replicas.unwrap_or_default() <= replicas)
+}
@@ -0,0 +1,78 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amp_common::config::CredentialConfiguration;
+use amp_common::schema::Actor;
+use oci_distribution::client::{Client, ClientConfig};
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+
+use super::error::{Error, Result};
+
+/// The image reference pinned by its content digest, in form of
+/// `{repository}@sha256:{hex}`, deployments run this exact image.
+pub const DIGEST_ANNOTATION_KEY: &str = "amphitheatre.app/image-digest";
+
+/// Resolve the image (tag) to a reference pinned by its content digest
+/// via the registry API.
+pub async fn resolve(configuration: &CredentialConfiguration, image: &str) -> Result<String> {
+    if image.contains('@') {
+        return Ok(image.to_string());
+    }
+
+    let reference: Reference = image.parse().map_err(|e| Error::RegistryError(format!("{}", e)))?;
+    let auth = auth(configuration, &reference);
+
+    let mut client = Client::new(ClientConfig::default());
+    let digest = client
+        .fetch_manifest_digest(&reference, &auth)
+        .await
+        .map_err(|e| Error::RegistryError(e.to_string()))?;
+    tracing::debug!("Resolved the digest of image {}: {}", image, digest);
+
+    Ok(format!("{}@{}", repository(image), digest))
+}
+
+/// Read the pinned image reference of this actor.
+pub fn of(actor: &Actor) -> Option<String> {
+    actor.metadata.annotations.as_ref()?.get(DIGEST_ANNOTATION_KEY).cloned()
+}
+
+/// The images of actors are pushed to the default registry, use its
+/// credential if the image belongs to it, else, pull anonymously.
+pub(crate) fn auth(configuration: &CredentialConfiguration, reference: &Reference) -> RegistryAuth {
+    if let Some(credential) = configuration.default_registry() {
+        let server = credential
+            .server
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap_or_default();
+
+        if server == reference.resolve_registry() || server == reference.registry() {
+            return RegistryAuth::Basic(credential.username_any(), credential.password_any());
+        }
+    }
+
+    RegistryAuth::Anonymous
+}
+
+/// Strip the tag from image, the port of registry is kept.
+fn repository(image: &str) -> &str {
+    match image.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => repository,
+        _ => image,
+    }
+}
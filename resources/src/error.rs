@@ -29,6 +29,12 @@ pub enum Error {
 
     #[error("UrlParseError: {0}")]
     UrlParseError(#[source] url::ParseError),
+
+    #[error("TomlParseError: {0}")]
+    TomlParseError(#[source] toml::de::Error),
+
+    #[error("RegistryError: {0}")]
+    RegistryError(String),
     // #[error("ApiError: {0}")]
     // ApiError(#[source] ApiError),
 }
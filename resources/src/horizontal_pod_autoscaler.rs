@@ -0,0 +1,183 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::autoscaling::v2::{
+    CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerSpec, MetricSpec, MetricTarget,
+    ResourceMetricSource,
+};
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+
+use super::error::{Error, Result};
+use super::settings::{self, AutoscalingSettings, Workload};
+use super::{hash, pod, LAST_APPLIED_HASH_KEY};
+
+pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
+}
+
+pub async fn create(client: &Client, actor: &Actor) -> Result<HorizontalPodAutoscaler> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let resource = new(actor)?;
+    tracing::debug!("The HorizontalPodAutoscaler resource:\n {:?}\n", resource);
+
+    let autoscaler = api
+        .create(&PostParams::default(), &resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Created HorizontalPodAutoscaler: {}", autoscaler.name_any());
+    Ok(autoscaler)
+}
+
+pub async fn update(client: &Client, actor: &Actor) -> Result<HorizontalPodAutoscaler> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    let mut autoscaler = api.get(&name).await.map_err(Error::KubeError)?;
+    tracing::debug!("The HorizontalPodAutoscaler {} already exists: {:?}", &name, autoscaler);
+
+    let settings = settings::of(actor)?;
+    let expected_hash = hash(&(&settings.deploy.kind, &settings.deploy.autoscaling))?;
+    let found_hash: String = autoscaler
+        .annotations()
+        .get(LAST_APPLIED_HASH_KEY)
+        .map_or("".into(), |v| v.into());
+
+    if found_hash != expected_hash {
+        let resource = new(actor)?;
+        tracing::debug!("The updating HorizontalPodAutoscaler resource:\n {:?}\n", resource);
+
+        autoscaler = api
+            .patch(
+                &name,
+                &PatchParams::apply("amp-controllers").force(),
+                &Patch::Apply(&resource),
+            )
+            .await
+            .map_err(Error::KubeError)?;
+
+        tracing::info!("Updated HorizontalPodAutoscaler: {}", autoscaler.name_any());
+    }
+
+    Ok(autoscaler)
+}
+
+/// Delete the HorizontalPodAutoscaler of actor if it exists, e.g. the autoscaling is turned off.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted HorizontalPodAutoscaler: {}", name);
+    }
+
+    Ok(())
+}
+
+/// Read the HorizontalPodAutoscaler of actor, it's absent if the actor is not autoscaled.
+pub async fn get(client: &Client, actor: &Actor) -> Result<Option<HorizontalPodAutoscaler>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<HorizontalPodAutoscaler> = Api::namespaced(client.clone(), namespace.as_str());
+
+    api.get_opt(&actor.name_any()).await.map_err(Error::KubeError)
+}
+
+fn new(actor: &Actor) -> Result<HorizontalPodAutoscaler> {
+    let name = actor.name_any();
+    let settings = settings::of(actor)?;
+    let autoscaling = settings.deploy.autoscaling.clone().unwrap_or_default();
+
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = pod::labels(actor);
+    let annotations = BTreeMap::from([(
+        LAST_APPLIED_HASH_KEY.into(),
+        hash(&(&settings.deploy.kind, &settings.deploy.autoscaling))?,
+    )]);
+
+    let kind = match settings.deploy.kind {
+        Workload::Deployment => "Deployment",
+        Workload::StatefulSet => "StatefulSet",
+    };
+
+    let resource = HorizontalPodAutoscaler {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels),
+            annotations: Some(annotations),
+            ..Default::default()
+        },
+        spec: Some(HorizontalPodAutoscalerSpec {
+            scale_target_ref: CrossVersionObjectReference {
+                api_version: Some("apps/v1".into()),
+                kind: kind.into(),
+                name,
+            },
+            min_replicas: autoscaling.min_replicas,
+            max_replicas: autoscaling.max_replicas,
+            metrics: Some(metrics(&autoscaling)).filter(|metrics| !metrics.is_empty()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    Ok(resource)
+}
+
+/// The resource metrics of the target utilizations, the HorizontalPodAutoscaler
+/// defaults to 80% of CPU if none of them is declared.
+fn metrics(autoscaling: &AutoscalingSettings) -> Vec<MetricSpec> {
+    [("cpu", autoscaling.cpu), ("memory", autoscaling.memory)]
+        .into_iter()
+        .filter_map(|(name, utilization)| {
+            utilization.map(|utilization| MetricSpec {
+                type_: "Resource".into(),
+                resource: Some(ResourceMetricSource {
+                    name: name.into(),
+                    target: MetricTarget {
+                        type_: "Utilization".into(),
+                        average_utilization: Some(utilization),
+                        ..Default::default()
+                    },
+                }),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
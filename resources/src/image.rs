@@ -14,13 +14,19 @@
 
 use amp_common::schema::Actor;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
-use kube::api::{Patch, PatchParams, PostParams};
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams};
 use kube::core::{DynamicObject, GroupVersionKind};
 use kube::discovery::ApiResource;
 use kube::{Api, Client, Resource, ResourceExt};
 use serde_json::{from_value, json};
 
 use super::error::{Error, Result};
+use super::{settings, sub_path};
+
+/// When the latest build of Image was started, kpack builds the Image
+/// every time its spec changed.
+const BUILD_STARTED_AT_KEY: &str = "amphitheatre.app/build-started-at";
 
 pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     let namespace = actor
@@ -32,13 +38,13 @@ pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
     Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
 }
 
-pub async fn create(client: &Client, actor: &Actor) -> Result<DynamicObject> {
+pub async fn create(client: &Client, actor: &Actor, binding: Option<String>) -> Result<DynamicObject> {
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
 
-    let resource = new(actor)?;
+    let resource = new(actor, binding)?;
     tracing::debug!("The Image resource:\n {:?}\n", resource);
 
     let image = api
@@ -51,7 +57,7 @@ pub async fn create(client: &Client, actor: &Actor) -> Result<DynamicObject> {
     Ok(image)
 }
 
-pub async fn update(client: &Client, actor: &Actor) -> Result<DynamicObject> {
+pub async fn update(client: &Client, actor: &Actor, binding: Option<String>) -> Result<DynamicObject> {
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
@@ -61,9 +67,14 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<DynamicObject> {
     let mut image = api.get(&name).await.map_err(Error::KubeError)?;
     tracing::debug!("The Image \"{}\" already exists:\n {:?}\n", name, image);
 
-    let resource = new(actor)?;
+    let resource = new(actor, binding)?;
 
-    if image.data.pointer("/spec") != resource.data.pointer("/spec") {
+    // The Images created before they were labeled are never watched, label them as well.
+    let labeled = resource
+        .labels()
+        .iter()
+        .all(|(key, value)| image.labels().get(key) == Some(value));
+    if !labeled || image.data.pointer("/spec") != resource.data.pointer("/spec") {
         tracing::debug!("The updating Image resource:\n {:?}\n", resource);
 
         image = api
@@ -82,18 +93,26 @@ pub async fn update(client: &Client, actor: &Actor) -> Result<DynamicObject> {
 }
 
 #[inline]
-fn api_resource() -> ApiResource {
+pub fn api_resource() -> ApiResource {
     ApiResource::from_gvk(&GroupVersionKind::gvk("kpack.io", "v1alpha2", "Image"))
 }
 
-fn new(actor: &Actor) -> Result<DynamicObject> {
+/// Create an Image, the Secret of build-time secrets is bound to builds if present.
+fn new(actor: &Actor, binding: Option<String>) -> Result<DynamicObject> {
     let owner_reference = actor.controller_owner_ref(&()).unwrap();
-    let resource = from_value(json!({
+    let mut resource = json!({
         "apiVersion": "kpack.io/v1alpha2",
         "kind": "Image",
         "metadata": {
             "name": actor.spec.build_name(),
-            "ownerReferences": vec![owner_reference]
+            "ownerReferences": vec![owner_reference],
+            "labels": {
+                "app.kubernetes.io/name": actor.spec.build_name(),
+                "app.kubernetes.io/managed-by": "Amphitheatre",
+            },
+            "annotations": {
+                BUILD_STARTED_AT_KEY: Utc::now().to_rfc3339(),
+            }
         },
         "spec": {
             "tag": actor.spec.docker_tag(),
@@ -107,13 +126,23 @@ fn new(actor: &Actor) -> Result<DynamicObject> {
                     "url": actor.spec.source.repo,
                     "revision": actor.spec.source.rev,
                 },
-                "subPath": actor.spec.source.path.as_deref().unwrap_or_default(),
+                "subPath": sub_path(actor.spec.source.path.as_deref()).unwrap_or_default(),
             }
         }
-    }))
-    .map_err(Error::SerializationError)?;
+    });
+
+    if let Some(name) = binding {
+        resource["spec"]["build"] = json!({
+            "services": [{ "apiVersion": "v1", "kind": "Secret", "name": name }]
+        });
+    }
+
+    // Cache the layers of builds in the registry, if the cache repository is configured.
+    if let Some(repo) = settings::of(actor)?.build.cache_repo {
+        resource["spec"]["cache"] = json!({ "registry": { "tag": format!("{}:{}", repo, actor.spec.name) } });
+    }
 
-    Ok(resource)
+    from_value(resource).map_err(Error::SerializationError)
 }
 
 pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
@@ -129,15 +158,145 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
         tracing::debug!("Found Image {}", &name);
         tracing::debug!("The Image data is: {:?}", image.data);
 
-        if let Some(condtions) = image.data.pointer("/status/conditions") {
-            let conditions: Vec<Condition> =
-                serde_json::from_value(json!(condtions)).map_err(Error::SerializationError)?;
-            return Ok(conditions
-                .iter()
-                .any(|condition| condition.type_ == "Ready" && condition.status == "True"));
-        }
+        return Ok(ready(&image)? == Some(true));
     }
 
     tracing::debug!("Not found Image {}", &name);
     Ok(false)
 }
+
+/// Check if the build image has failed or timed out, returns the reason.
+pub async fn failed(client: &Client, actor: &Actor) -> Result<Option<String>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
+    let name = actor.spec.build_name();
+
+    let image = match api.get_opt(&name).await.map_err(Error::KubeError)? {
+        Some(image) => image,
+        None => return Ok(None),
+    };
+
+    match ready(&image)? {
+        Some(true) => return Ok(None),
+        Some(false) => return Ok(Some("BuildFailed".into())),
+        None => {}
+    }
+
+    // The build is still running, check if it has timed out.
+    let timeout = settings::of(actor)?.build.timeout();
+    if let Some(started_at) = started_at(&image) {
+        let elapsed = Utc::now().signed_duration_since(started_at);
+        if elapsed.num_seconds() > timeout.as_secs() as i64 {
+            return Ok(Some("BuildTimeout".into()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// When the latest build of Image was started, none if it's not found.
+pub async fn started(client: &Client, actor: &Actor) -> Result<Option<DateTime<Utc>>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
+    let name = actor.spec.build_name();
+
+    Ok(api
+        .get_opt(&name)
+        .await
+        .map_err(Error::KubeError)?
+        .and_then(|image| started_at(&image)))
+}
+
+#[inline]
+fn started_at(image: &DynamicObject) -> Option<DateTime<Utc>> {
+    image
+        .annotations()
+        .get(BUILD_STARTED_AT_KEY)
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|value| value.with_timezone(&Utc))
+}
+
+/// The status of the Ready condition of Image, it's unknown (still building)
+/// until kpack has observed the current generation, otherwise the condition
+/// is left over from the previous build.
+fn ready(image: &DynamicObject) -> Result<Option<bool>> {
+    let observed = image
+        .data
+        .pointer("/status/observedGeneration")
+        .and_then(|value| value.as_i64());
+    if observed.is_none() || observed != image.metadata.generation {
+        return Ok(None);
+    }
+
+    let mut conditions: Vec<Condition> = vec![];
+    if let Some(items) = image.data.pointer("/status/conditions") {
+        conditions = serde_json::from_value(json!(items)).map_err(Error::SerializationError)?;
+    }
+
+    let ready = conditions.iter().find(|condition| condition.type_ == "Ready");
+    Ok(match ready.map(|condition| condition.status.as_str()) {
+        Some("True") => Some(true),
+        Some("False") => Some(false),
+        _ => None,
+    })
+}
+
+/// Delete the Image, kpack would schedule a new build for the Image
+/// if only its in-flight Build is deleted.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<DynamicObject> = Api::namespaced_with(client.clone(), namespace.as_str(), &api_resource());
+    let name = actor.spec.build_name();
+
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::background())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted Image: {}", name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(generation: i64, observed: i64, status: &str) -> DynamicObject {
+        from_value(json!({
+            "apiVersion": "kpack.io/v1alpha2",
+            "kind": "Image",
+            "metadata": { "name": "app", "generation": generation },
+            "status": {
+                "observedGeneration": observed,
+                "conditions": [{
+                    "type": "Ready",
+                    "status": status,
+                    "reason": "",
+                    "message": "",
+                    "lastTransitionTime": "2023-01-01T00:00:00Z",
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_ready() {
+        assert_eq!(ready(&image(2, 2, "True")).unwrap(), Some(true));
+        assert_eq!(ready(&image(2, 2, "False")).unwrap(), Some(false));
+        assert_eq!(ready(&image(2, 2, "Unknown")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_ready_stale_conditions() {
+        assert_eq!(ready(&image(3, 2, "True")).unwrap(), None);
+        assert_eq!(ready(&image(3, 2, "False")).unwrap(), None);
+    }
+}
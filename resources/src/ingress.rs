@@ -0,0 +1,220 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule, IngressServiceBackend, IngressSpec,
+    IngressTLS, ServiceBackendPort,
+};
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+
+use super::configuration::IngressConfiguration;
+use super::error::{Error, Result};
+use super::settings::{self, ExposeSettings};
+use super::{hash, pod, LAST_APPLIED_HASH_KEY};
+
+/// The URLs of the exposed ports of actor (in JSON), they're returned by the API.
+pub const URLS_ANNOTATION_KEY: &str = "amphitheatre.app/urls";
+
+/// The annotation requests the certificate of hosts from the cert-manager ClusterIssuer.
+const CLUSTER_ISSUER_ANNOTATION_KEY: &str = "cert-manager.io/cluster-issuer";
+
+pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
+}
+
+pub async fn create(client: &Client, actor: &Actor, configuration: &IngressConfiguration) -> Result<Ingress> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let resource = new(actor, configuration)?;
+    tracing::debug!("The Ingress resource:\n {:?}\n", resource);
+
+    let ingress = api
+        .create(&PostParams::default(), &resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Created Ingress: {}", ingress.name_any());
+    Ok(ingress)
+}
+
+pub async fn update(client: &Client, actor: &Actor, configuration: &IngressConfiguration) -> Result<Ingress> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    let mut ingress = api.get(&name).await.map_err(Error::KubeError)?;
+    tracing::debug!("The Ingress {} already exists: {:?}", &name, ingress);
+
+    let resource = new(actor, configuration)?;
+    let expected_hash = resource.annotations().get(LAST_APPLIED_HASH_KEY);
+    let found_hash = ingress.annotations().get(LAST_APPLIED_HASH_KEY);
+
+    if found_hash != expected_hash {
+        tracing::debug!("The updating Ingress resource:\n {:?}\n", resource);
+
+        ingress = api
+            .patch(
+                &name,
+                &PatchParams::apply("amp-controllers").force(),
+                &Patch::Apply(&resource),
+            )
+            .await
+            .map_err(Error::KubeError)?;
+
+        tracing::info!("Updated Ingress: {}", ingress.name_any());
+    }
+
+    Ok(ingress)
+}
+
+/// Delete the Ingress of actor if it exists, e.g. no ports are exposed any more.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted Ingress: {}", name);
+    }
+
+    Ok(())
+}
+
+/// The public URLs of the exposed ports of actor.
+pub fn urls(actor: &Actor, configuration: &IngressConfiguration) -> Result<Vec<String>> {
+    let scheme = if configuration.issuer.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
+    Ok(settings::of(actor)?
+        .deploy
+        .exposes
+        .iter()
+        .map(|expose| format!("{}://{}{}", scheme, host(actor, configuration, expose), path(expose)))
+        .collect())
+}
+
+/// The hostname of the exposed port, `{host}.{playbook}.{domain}`.
+fn host(actor: &Actor, configuration: &IngressConfiguration, expose: &ExposeSettings) -> String {
+    let name = expose.host.clone().unwrap_or_else(|| actor.name_any());
+    let playbook = actor
+        .owner_references()
+        .iter()
+        .find(|owner| owner.kind == "Playbook")
+        .map(|owner| owner.name.clone())
+        .or_else(|| actor.namespace())
+        .unwrap_or_default();
+
+    format!("{}.{}.{}", name, playbook, configuration.domain)
+}
+
+fn path(expose: &ExposeSettings) -> String {
+    expose.path.clone().unwrap_or_else(|| "/".into())
+}
+
+fn new(actor: &Actor, configuration: &IngressConfiguration) -> Result<Ingress> {
+    let name = actor.name_any();
+    let exposes = settings::of(actor)?.deploy.exposes;
+
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = pod::labels(actor);
+
+    let mut annotations = configuration.annotations.clone();
+    annotations.insert(
+        LAST_APPLIED_HASH_KEY.into(),
+        hash(&(&exposes, configuration, actor.owner_references()))?,
+    );
+    if let Some(issuer) = &configuration.issuer {
+        annotations.insert(CLUSTER_ISSUER_ANNOTATION_KEY.into(), issuer.clone());
+    }
+
+    // The exposed ports sharing a host are routed by the path prefixes.
+    let mut paths: BTreeMap<String, Vec<HTTPIngressPath>> = BTreeMap::new();
+    for expose in exposes.iter() {
+        paths
+            .entry(host(actor, configuration, expose))
+            .or_default()
+            .push(HTTPIngressPath {
+                path: Some(path(expose)),
+                path_type: "Prefix".into(),
+                backend: IngressBackend {
+                    service: Some(IngressServiceBackend {
+                        name: name.clone(),
+                        port: Some(ServiceBackendPort {
+                            number: Some(expose.port),
+                            ..Default::default()
+                        }),
+                    }),
+                    ..Default::default()
+                },
+            });
+    }
+
+    // The certificate of hosts is issued into the Secret by cert-manager.
+    let tls = configuration.issuer.as_ref().map(|_| {
+        vec![IngressTLS {
+            hosts: Some(paths.keys().cloned().collect()),
+            secret_name: Some(format!("{}-tls", name)),
+        }]
+    });
+
+    let rules = paths
+        .into_iter()
+        .map(|(host, paths)| IngressRule {
+            host: Some(host),
+            http: Some(HTTPIngressRuleValue { paths }),
+        })
+        .collect();
+
+    let resource = Ingress {
+        metadata: ObjectMeta {
+            name: Some(name),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels),
+            annotations: Some(annotations),
+            ..Default::default()
+        },
+        spec: Some(IngressSpec {
+            ingress_class_name: configuration.class_name.clone(),
+            rules: Some(rules),
+            tls,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    Ok(resource)
+}
@@ -13,36 +13,39 @@
 // limitations under the License.
 
 use std::collections::BTreeMap;
+use std::time::Duration;
 
-use amp_common::schema::{Actor, ActorSpec};
+use amp_common::schema::Actor;
 use k8s_openapi::api::batch::v1::{Job, JobSpec};
-use k8s_openapi::api::core::v1::{
-    Container, KeyToPath, PodSpec, PodTemplateSpec, SecretVolumeSource, Volume, VolumeMount,
-};
-use kube::api::{Patch, PatchParams, PostParams};
+use k8s_openapi::api::core::v1::{PodSpec, PodTemplateSpec};
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::{DeleteParams, ListParams, PostParams};
 use kube::core::ObjectMeta;
+use kube::runtime::wait::{await_condition, conditions};
 use kube::{Api, Client, Resource, ResourceExt};
+use tokio::time::timeout;
 
 use super::error::{Error, Result};
-use super::{hash, DEFAULT_KANIKO_IMAGE, LAST_APPLIED_HASH_KEY};
+use super::{hash, settings, LAST_APPLIED_HASH_KEY};
 
-pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
+/// How long to wait for the old Job to be deleted before replacing it.
+const DELETION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn exists(client: &Client, actor: &Actor, name: &str) -> Result<bool> {
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = actor.spec.build_name();
 
-    Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
+    Ok(api.get_opt(name).await.map_err(Error::KubeError)?.is_some())
 }
 
-pub async fn create(client: &Client, actor: &Actor) -> Result<Job> {
+pub async fn create(client: &Client, actor: &Actor, resource: Job) -> Result<Job> {
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
 
-    let resource = new(actor)?;
     tracing::debug!("The Job resource:\n {:?}\n", resource);
 
     let job = api
@@ -54,52 +57,60 @@ pub async fn create(client: &Client, actor: &Actor) -> Result<Job> {
     Ok(job)
 }
 
-pub async fn update(client: &Client, actor: &Actor) -> Result<Job> {
+pub async fn update(client: &Client, actor: &Actor, resource: Job) -> Result<Job> {
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = actor.spec.build_name();
+    let name = resource.name_any();
 
-    let mut job = api.get(&name).await.map_err(Error::KubeError)?;
+    let job = match api.get_opt(&name).await.map_err(Error::KubeError)? {
+        Some(job) => job,
+        None => return create(client, actor, resource).await,
+    };
     tracing::debug!("The Job {} already exists: {:?}", &name, job);
 
-    let expected_hash = hash(&actor.spec)?;
-    let found_hash: String = job
-        .annotations()
-        .get(LAST_APPLIED_HASH_KEY)
-        .map_or("".into(), |v| v.into());
-
-    if found_hash != expected_hash {
-        let resource = new(actor)?;
-        tracing::debug!("The updating Job resource:\n {:?}\n", resource);
-
-        job = api
-            .patch(
-                &name,
-                &PatchParams::apply("amp-controllers").force(),
-                &Patch::Apply(&resource),
-            )
+    let expected_hash = resource.annotations().get(LAST_APPLIED_HASH_KEY);
+    if job.annotations().get(LAST_APPLIED_HASH_KEY) == expected_hash {
+        return Ok(job);
+    }
+
+    tracing::debug!("The updating Job resource:\n {:?}\n", resource);
+
+    // The pod template of Job is immutable, replace it with a new one once the
+    // old one is gone, else, leave it to the next reconciliation.
+    if job.metadata.deletion_timestamp.is_none() {
+        api.delete(&name, &DeleteParams::background())
             .await
             .map_err(Error::KubeError)?;
+    }
 
-        tracing::info!("Updated Job: {}", job.name_any());
+    let uid = job.uid().unwrap_or_default();
+    let deleted = await_condition(api.clone(), &name, conditions::is_deleted(&uid));
+    if !matches!(timeout(DELETION_TIMEOUT, deleted).await, Ok(Ok(_))) {
+        tracing::warn!("The Job {} is still being deleted, replace it later", name);
+        return Ok(job);
     }
 
+    let job = api
+        .create(&PostParams::default(), &resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Updated Job: {}", job.name_any());
     Ok(job)
 }
 
-/// Create a Job for build images
-fn new(actor: &Actor) -> Result<Job> {
-    let name = actor.spec.build_name();
+/// Create a Job for build images, runs the pod of the build backend.
+/// All the Jobs of a build are labeled with the build name.
+pub fn new(actor: &Actor, name: String, spec: PodSpec) -> Result<Job> {
     let owner_reference = actor.controller_owner_ref(&()).unwrap();
-    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&actor.spec)?)]);
     let labels = BTreeMap::from([
-        ("app.kubernetes.io/name".into(), name.clone()),
+        ("app.kubernetes.io/name".into(), actor.spec.build_name()),
         ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
     ]);
 
-    let container = new_kaniko_container(&actor.spec)?;
+    let settings = settings::of(actor)?;
     let template = PodTemplateSpec {
         metadata: Some(ObjectMeta {
             labels: Some(labels.clone()),
@@ -107,24 +118,21 @@ fn new(actor: &Actor) -> Result<Job> {
         }),
         spec: Some(PodSpec {
             restart_policy: Some("Never".into()),
-            containers: vec![container],
-            volumes: Some(vec![Volume {
-                name: "kaniko-secret".to_string(),
-                secret: Some(SecretVolumeSource {
-                    secret_name: Some("amp-registry-credentials".to_string()),
-                    items: Some(vec![KeyToPath {
-                        key: ".dockerconfigjson".to_string(),
-                        path: "config.json".to_string(),
-                        ..Default::default()
-                    }]),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            }]),
-            ..Default::default()
+            ..spec
         }),
     };
 
+    let spec = JobSpec {
+        template,
+        backoff_limit: Some(0),
+        active_deadline_seconds: Some(settings.build.timeout().as_secs() as i64),
+        ..Default::default()
+    };
+
+    // The Job is replaced only when the build is changed, the deploy-time
+    // settings of actor are irrelevant to it.
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&(&settings.build, &spec))?)]);
+
     let resource = Job {
         metadata: ObjectMeta {
             name: Some(name),
@@ -133,64 +141,22 @@ fn new(actor: &Actor) -> Result<Job> {
             annotations: Some(annotations),
             ..Default::default()
         },
-        spec: Some(JobSpec {
-            template,
-            backoff_limit: Some(0),
-            ..Default::default()
-        }),
+        spec: Some(spec),
         ..Default::default()
     };
 
     Ok(resource)
 }
 
-#[inline]
-fn context(spec: &ActorSpec) -> String {
-    format!("{}#{}", spec.source.repo.replace("https", "git"), spec.source.rev())
-}
-
-fn new_kaniko_container(spec: &ActorSpec) -> Result<Container> {
-    let mut args: Vec<String> = vec![
-        ("context", context(spec)),
-        ("destination", spec.docker_tag()),
-        ("verbosity", "trace".to_string()),
-        ("cache", "true".to_string()),
-    ]
-    .iter()
-    .map(|(key, value)| format!("--{}={}", key, value))
-    .collect();
-
-    if let Some(argments) = spec.build_args() {
-        args.extend(argments);
-    }
-
-    let container = Container {
-        name: "build".to_string(),
-        image: Some(DEFAULT_KANIKO_IMAGE.to_string()),
-        image_pull_policy: Some("Always".into()),
-        args: Some(args),
-        env: spec.build_env(),
-        volume_mounts: Some(vec![VolumeMount {
-            name: "kaniko-secret".to_string(),
-            mount_path: "/kaniko/.docker".to_string(),
-            ..Default::default()
-        }]),
-        ..Default::default()
-    };
-
-    Ok(container)
-}
-
-pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
+pub async fn completed(client: &Client, actor: &Actor, name: &str) -> Result<bool> {
     tracing::debug!("Check If the build Job has not completed");
 
     let namespace = actor
         .namespace()
         .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
     let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
-    let name = actor.spec.build_name();
 
-    if let Ok(Some(job)) = api.get_opt(&name).await {
+    if let Ok(Some(job)) = api.get_opt(name).await {
         tracing::debug!("Found Job {}", &name);
         Ok(job.status.map_or(false, |s| s.succeeded >= Some(1)))
     } else {
@@ -198,3 +164,62 @@ pub async fn completed(client: &Client, actor: &Actor) -> Result<bool> {
         Ok(false)
     }
 }
+
+/// Check if the build Job has failed, returns the reason.
+pub async fn failed(client: &Client, actor: &Actor, name: &str) -> Result<Option<String>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let conditions = api
+        .get_opt(name)
+        .await
+        .map_err(Error::KubeError)?
+        .and_then(|job| job.status)
+        .and_then(|status| status.conditions)
+        .unwrap_or_default();
+
+    let reason = conditions
+        .iter()
+        .find(|condition| condition.type_ == "Failed" && condition.status == "True")
+        .map(|condition| match condition.reason.as_deref() {
+            Some("DeadlineExceeded") => "BuildTimeout".to_string(),
+            _ => "BuildFailed".to_string(),
+        });
+
+    Ok(reason)
+}
+
+/// When the build Job was started, none if it's not found.
+pub async fn started(client: &Client, actor: &Actor, name: &str) -> Result<Option<DateTime<Utc>>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let started = api.get_opt(name).await.map_err(Error::KubeError)?.and_then(|job| {
+        job.status
+            .and_then(|status| status.start_time)
+            .or(job.metadata.creation_timestamp)
+    });
+
+    Ok(started.map(|time| time.0))
+}
+
+/// Delete all the build Jobs of actor and their pods.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Job> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.spec.build_name();
+
+    let params = ListParams::default().labels(&format!("app.kubernetes.io/name={}", name));
+    api.delete_collection(&DeleteParams::background(), &params)
+        .await
+        .map_err(Error::KubeError)?;
+    tracing::info!("Deleted the Jobs of build: {}", name);
+
+    Ok(())
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use kube::{Resource, ResourceExt};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::to_string;
 use sha2::{Digest, Sha256};
@@ -19,20 +21,35 @@ use sha2::{Digest, Sha256};
 use self::error::{Error, Result};
 
 pub mod actor;
+pub mod builder;
+pub mod config_map;
+pub mod configuration;
 pub mod credential;
 pub mod deployment;
+pub mod digest;
 pub mod error;
 pub mod event;
+pub mod horizontal_pod_autoscaler;
 pub mod image;
+pub mod ingress;
 pub mod job;
 pub mod namespace;
+pub mod network_policy;
+pub mod persistent_volume_claim;
 pub mod playbook;
+pub mod pod;
+pub mod retention;
 pub mod secret;
 pub mod service;
 pub mod service_account;
+pub mod settings;
+pub mod stateful_set;
 
 const LAST_APPLIED_HASH_KEY: &str = "amphitheatre.app/last-applied-hash";
 const DEFAULT_KANIKO_IMAGE: &str = "gcr.io/kaniko-project/executor:v1.9.1";
+const DEFAULT_BUILDKIT_IMAGE: &str = "moby/buildkit:v0.11.6-rootless";
+const DEFAULT_WARMER_IMAGE: &str = "gcr.io/kaniko-project/warmer:v1.9.1";
+const DEFAULT_CRANE_IMAGE: &str = "gcr.io/go-containerregistry/crane:v0.15.2";
 
 pub fn hash<T>(resource: &T) -> Result<String>
 where
@@ -43,3 +60,42 @@ where
 
     Ok(format!("{:x}", hash))
 }
+
+/// The sub-path of character for the path of source, it's the directory
+/// of manifest if the path is the manifest file (ends with `.toml`), which
+/// is kept for the sources declared before workspaces, none for the root.
+pub fn sub_path(path: Option<&str>) -> Option<&str> {
+    let path = path.map(|p| p.trim_matches('/')).filter(|p| !p.is_empty())?;
+    if !path.ends_with(".toml") {
+        return Some(path);
+    }
+
+    path.rsplit_once('/').map(|(directory, _)| directory)
+}
+
+/// Read the JSON encoded annotation of the resource,
+/// returns the default value if it's absent.
+pub fn annotation<K, T>(resource: &K, key: &str) -> Result<T>
+where
+    K: Resource,
+    T: DeserializeOwned + Default,
+{
+    match resource.annotations().get(key) {
+        Some(value) => serde_json::from_str(value).map_err(Error::SerializationError),
+        None => Ok(T::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sub_path() {
+        assert_eq!(sub_path(None), None);
+        assert_eq!(sub_path(Some("")), None);
+        assert_eq!(sub_path(Some(".amp.toml")), None);
+        assert_eq!(sub_path(Some("services/api")), Some("services/api"));
+        assert_eq!(sub_path(Some("/services/api/.amp.toml")), Some("services/api"));
+    }
+}
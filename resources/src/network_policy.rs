@@ -0,0 +1,225 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use amp_common::schema::{ActorSpec, Playbook};
+use k8s_openapi::api::networking::v1::{
+    NetworkPolicy, NetworkPolicyIngressRule, NetworkPolicyPeer, NetworkPolicyPort, NetworkPolicySpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+
+use super::configuration::IngressConfiguration;
+use super::error::{Error, Result};
+use super::{hash, playbook, LAST_APPLIED_HASH_KEY};
+
+/// The NetworkPolicy denies all the ingress traffic to the pods of playbook,
+/// unless it's allowed by the policies of actors.
+pub const DEFAULT_DENY_NAME: &str = "amp-default-deny";
+
+/// The prefix of the NetworkPolicy names of actors, so that they can't collide
+/// with the default deny policy or the other policies in the namespace.
+const ALLOW_NAME_PREFIX: &str = "amp-allow-";
+
+/// The label of the namespaces, it's set by Kubernetes automatically.
+const NAMESPACE_NAME_LABEL_KEY: &str = "kubernetes.io/metadata.name";
+
+/// Deny all the ingress traffic to the pods in the namespace of playbook,
+/// including the traffic from the other namespaces.
+pub async fn isolate(client: &Client, playbook: &Playbook) -> Result<()> {
+    let resource = new(
+        playbook,
+        DEFAULT_DENY_NAME,
+        NetworkPolicySpec {
+            pod_selector: LabelSelector::default(),
+            policy_types: Some(vec!["Ingress".into()]),
+            ..Default::default()
+        },
+    )?;
+
+    apply(client, playbook, resource).await
+}
+
+/// Allow the traffic between the actors of playbook which are partners of each
+/// other (in either direction), and from the ingress controller to the exposed
+/// ports of actors if its namespace is configured. The policies of actors no
+/// longer in the playbook are deleted.
+pub async fn allow(client: &Client, playbook: &Playbook, ingress: Option<&IngressConfiguration>) -> Result<()> {
+    let actors = playbook.spec.actors.clone().unwrap_or_default();
+    let settings = playbook::settings(playbook)?;
+    if ingress.map_or(false, |ingress| ingress.namespace.is_none()) {
+        tracing::warn!("The namespace of ingress controller is absent, the exposed ports are not reachable");
+    }
+
+    // The partnerships are symmetric, the partner may call back the actor.
+    let mut peers: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for actor in actors.iter() {
+        // The replicas of actor may talk to each other, e.g. the members of a cluster.
+        peers.entry(actor.name.clone()).or_default().insert(actor.name.clone());
+        for name in actor.partners.iter().flat_map(|partners| partners.keys()) {
+            peers.entry(actor.name.clone()).or_default().insert(name.clone());
+            peers.entry(name.clone()).or_default().insert(actor.name.clone());
+        }
+    }
+
+    for actor in actors.iter() {
+        let mut rules = vec![NetworkPolicyIngressRule {
+            from: Some(vec![NetworkPolicyPeer {
+                pod_selector: Some(LabelSelector {
+                    match_expressions: Some(vec![LabelSelectorRequirement {
+                        key: "app.kubernetes.io/name".into(),
+                        operator: "In".into(),
+                        values: peers.get(&actor.name).map(|names| names.iter().cloned().collect()),
+                    }]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }];
+
+        // The exposed ports accept the traffic from the ingress controller.
+        let exposes = settings
+            .get(&actor.name)
+            .map(|settings| settings.deploy.exposes.clone())
+            .unwrap_or_default();
+        let namespace = ingress.and_then(|ingress| ingress.namespace.as_ref());
+        if let (Some(namespace), false) = (namespace, exposes.is_empty()) {
+            let namespace_selector = LabelSelector {
+                match_labels: Some(BTreeMap::from([(NAMESPACE_NAME_LABEL_KEY.into(), namespace.clone())])),
+                ..Default::default()
+            };
+            rules.push(NetworkPolicyIngressRule {
+                from: Some(vec![NetworkPolicyPeer {
+                    namespace_selector: Some(namespace_selector),
+                    ..Default::default()
+                }]),
+                ports: Some(
+                    exposes
+                        .iter()
+                        .map(|expose| NetworkPolicyPort {
+                            port: Some(target_port(actor, expose.port)),
+                            protocol: Some("TCP".into()),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+            });
+        }
+
+        let spec = NetworkPolicySpec {
+            pod_selector: LabelSelector {
+                match_labels: Some(BTreeMap::from([("app.kubernetes.io/name".into(), actor.name.clone())])),
+                ..Default::default()
+            },
+            policy_types: Some(vec!["Ingress".into()]),
+            ingress: Some(rules),
+            ..Default::default()
+        };
+        apply(client, playbook, new(playbook, &name(&actor.name), spec)?).await?;
+    }
+
+    // Delete the policies of the actors which are removed from the playbook.
+    let api: Api<NetworkPolicy> = Api::namespaced(client.clone(), &playbook.spec.namespace);
+    let params = ListParams::default().labels("app.kubernetes.io/managed-by=Amphitheatre");
+    let names: BTreeSet<String> = actors.iter().map(|actor| name(&actor.name)).collect();
+    for policy in api.list(&params).await.map_err(Error::KubeError)? {
+        let name = policy.name_any();
+        if name != DEFAULT_DENY_NAME && !names.contains(&name) {
+            api.delete(&name, &DeleteParams::default())
+                .await
+                .map_err(Error::KubeError)?;
+            tracing::info!("Deleted NetworkPolicy: {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// The port of container which the exposed port of Service targets, the
+/// policies are applied to the pods, not the Service.
+fn target_port(actor: &ActorSpec, port: i32) -> IntOrString {
+    actor
+        .service_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|service_port| service_port.port == port)
+        .and_then(|service_port| service_port.target_port)
+        .unwrap_or(IntOrString::Int(port))
+}
+
+/// The name of the NetworkPolicy of actor.
+fn name(actor: &str) -> String {
+    format!("{}{}", ALLOW_NAME_PREFIX, actor)
+}
+
+/// Apply the NetworkPolicy if it has been changed.
+async fn apply(client: &Client, playbook: &Playbook, resource: NetworkPolicy) -> Result<()> {
+    let api: Api<NetworkPolicy> = Api::namespaced(client.clone(), &playbook.spec.namespace);
+    let name = resource.name_any();
+
+    let found_hash = api
+        .get_opt(&name)
+        .await
+        .map_err(Error::KubeError)?
+        .and_then(|policy| policy.annotations().get(LAST_APPLIED_HASH_KEY).cloned());
+    if found_hash.as_ref() == resource.annotations().get(LAST_APPLIED_HASH_KEY) {
+        return Ok(());
+    }
+
+    tracing::debug!("The applying NetworkPolicy resource:\n {:?}\n", resource);
+    api.patch(
+        &name,
+        &PatchParams::apply("amp-controllers").force(),
+        &Patch::Apply(&resource),
+    )
+    .await
+    .map_err(Error::KubeError)?;
+
+    tracing::info!("Applied NetworkPolicy: {}", name);
+    Ok(())
+}
+
+fn new(playbook: &Playbook, name: &str, spec: NetworkPolicySpec) -> Result<NetworkPolicy> {
+    let owner_reference = playbook.controller_owner_ref(&()).unwrap();
+    let labels = BTreeMap::from([("app.kubernetes.io/managed-by".into(), "Amphitheatre".into())]);
+    let annotations = BTreeMap::from([(LAST_APPLIED_HASH_KEY.into(), hash(&spec)?)]);
+
+    Ok(NetworkPolicy {
+        metadata: ObjectMeta {
+            name: Some(name.into()),
+            namespace: Some(playbook.spec.namespace.clone()),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels),
+            annotations: Some(annotations),
+            ..Default::default()
+        },
+        spec: Some(spec),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(name("web"), "amp-allow-web");
+        assert_ne!(name("default-deny"), DEFAULT_DENY_NAME);
+    }
+}
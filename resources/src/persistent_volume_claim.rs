@@ -0,0 +1,157 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::core::v1::{
+    PersistentVolume, PersistentVolumeClaim, PersistentVolumeClaimSpec, ResourceRequirements,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{ListParams, Patch, PatchParams, PostParams};
+use kube::core::ObjectMeta;
+use kube::{Api, Client, Resource, ResourceExt};
+use serde_json::json;
+
+use super::error::{Error, Result};
+use super::pod;
+use super::settings::{self, VolumePolicy, VolumeSettings};
+
+/// The label of claims which carries the name of the declared volume.
+pub const VOLUME_LABEL_KEY: &str = "amphitheatre.app/volume";
+
+pub async fn exists(client: &Client, namespace: &str, name: &str) -> Result<bool> {
+    let api: Api<PersistentVolumeClaim> = Api::namespaced(client.clone(), namespace);
+    Ok(api.get_opt(name).await.map_err(Error::KubeError)?.is_some())
+}
+
+pub async fn create(
+    client: &Client,
+    namespace: &str,
+    resource: &PersistentVolumeClaim,
+) -> Result<PersistentVolumeClaim> {
+    let api: Api<PersistentVolumeClaim> = Api::namespaced(client.clone(), namespace);
+    tracing::debug!("The PersistentVolumeClaim resource:\n {:?}\n", resource);
+
+    let claim = api
+        .create(&PostParams::default(), resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Created PersistentVolumeClaim: {}", claim.name_any());
+    Ok(claim)
+}
+
+/// The spec of PersistentVolumeClaim for the volume declared in the manifest.
+pub fn spec(volume: &VolumeSettings) -> PersistentVolumeClaimSpec {
+    PersistentVolumeClaimSpec {
+        access_modes: Some(vec![volume
+            .access_mode
+            .clone()
+            .unwrap_or_else(|| "ReadWriteOnce".into())]),
+        storage_class_name: volume.storage_class.clone(),
+        resources: Some(ResourceRequirements {
+            requests: Some(BTreeMap::from([("storage".into(), Quantity(volume.size.clone()))])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// The name of PersistentVolumeClaim for the volume of the actor.
+pub fn name(actor: &Actor, volume: &VolumeSettings) -> String {
+    format!("{}-{}", actor.name_any(), volume.name)
+}
+
+/// The labels of claims, the pod labels plus the name of the volume.
+pub fn labels(actor: &Actor, volume: &VolumeSettings) -> BTreeMap<String, String> {
+    let mut labels = pod::labels(actor);
+    labels.insert(VOLUME_LABEL_KEY.into(), volume.name.clone());
+    labels
+}
+
+/// Create the missing claims of the volumes declared for a Deployment actor,
+/// they're owned by the actor and removed with it.
+pub async fn apply(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+
+    for volume in settings::of(actor)?.deploy.volumes.iter() {
+        // The spec of claim is mostly immutable, so the existing one is kept as it is.
+        if exists(client, &namespace, &name(actor, volume)).await? {
+            continue;
+        }
+        create(client, &namespace, &new(actor, volume)).await?;
+    }
+
+    Ok(())
+}
+
+/// Keep the data of volumes with the `retain` policy after the playbook is deleted.
+///
+/// The claims are deleted along with the namespace of playbook anyway, so the
+/// reclaim policy of the bound PersistentVolumes is changed to `Retain` instead.
+/// The claims which are not bound yet will be handled in the next reconciliation.
+pub async fn retain(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let volumes = settings::of(actor)?.deploy.volumes;
+    if !volumes.iter().any(|volume| volume.policy == VolumePolicy::Retain) {
+        return Ok(());
+    }
+
+    let api: Api<PersistentVolumeClaim> = Api::namespaced(client.clone(), &namespace);
+    let params = ListParams::default().labels(&format!("app.kubernetes.io/name={}", actor.name_any()));
+    let claims = api.list(&params).await.map_err(Error::KubeError)?;
+
+    let persistent_volumes: Api<PersistentVolume> = Api::all(client.clone());
+    for claim in claims {
+        let retained = claim.labels().get(VOLUME_LABEL_KEY).map_or(false, |name| {
+            volumes
+                .iter()
+                .any(|volume| &volume.name == name && volume.policy == VolumePolicy::Retain)
+        });
+        let bound = claim.spec.as_ref().and_then(|spec| spec.volume_name.clone());
+        let name = match bound {
+            Some(name) if retained => name,
+            _ => continue,
+        };
+
+        let patch = json!({"spec": {"persistentVolumeReclaimPolicy": "Retain"}});
+        persistent_volumes
+            .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Retained PersistentVolume {} of claim {}", name, claim.name_any());
+    }
+
+    Ok(())
+}
+
+fn new(actor: &Actor, volume: &VolumeSettings) -> PersistentVolumeClaim {
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+
+    PersistentVolumeClaim {
+        metadata: ObjectMeta {
+            name: Some(name(actor, volume)),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels(actor, volume)),
+            ..Default::default()
+        },
+        spec: Some(spec(volume)),
+        ..Default::default()
+    }
+}
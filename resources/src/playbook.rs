@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 
 use amp_common::schema::{ActorSpec, Playbook, PlaybookState};
@@ -20,11 +21,25 @@ use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
 use kube::api::{DeleteParams, ListParams, Patch, PatchParams, PostParams};
 use kube::core::ObjectList;
 use kube::{Api, Client, CustomResourceExt, ResourceExt};
-use serde_json::{json, to_string_pretty};
+use serde::Serialize;
+use serde_json::{json, to_string, to_string_pretty, Value};
 use server::pkg::apis::apiextensions::v1::CustomResourceDefinition;
 use tokio::time::sleep;
 
+use super::annotation;
 use super::error::{Error, Result};
+use super::settings::{Settings, SETTINGS_ANNOTATION_KEY};
+
+/// The playbook-level variables for the `${var}` placeholders in manifests.
+pub const VARIABLES_ANNOTATION_KEY: &str = "amphitheatre.app/variables";
+/// The playbook-level overrides merged onto the actor specs, keyed by actor name.
+pub const OVERRIDES_ANNOTATION_KEY: &str = "amphitheatre.app/overrides";
+/// The lock which pins the resolved revisions and manifests of actors.
+pub const LOCK_ANNOTATION_KEY: &str = "amphitheatre.app/lock";
+/// Refuse to move the actors to newer commits than the locked ones if it's "true".
+pub const LOCKED_ANNOTATION_KEY: &str = "amphitheatre.app/locked";
+/// The repository for the build cache of actors in this playbook.
+pub const CACHE_REPO_ANNOTATION_KEY: &str = "amphitheatre.app/cache-repo";
 
 pub async fn install(client: &Client) -> Result<()> {
     let api: Api<CustomResourceDefinition> = Api::all(client.clone());
@@ -86,17 +101,86 @@ pub async fn create(client: &Client, playbook: &Playbook) -> Result<Playbook> {
     Ok(playbook)
 }
 
-pub async fn add(client: &Client, playbook: &Playbook, actor: ActorSpec) -> Result<()> {
+/// Add the actors to this playbook, the existing ones with the same name are replaced.
+pub async fn add(client: &Client, playbook: &Playbook, items: Vec<ActorSpec>) -> Result<()> {
     let api: Api<Playbook> = Api::all(client.clone());
-    let actor_name = actor.name.clone();
+    let names: Vec<String> = items.iter().map(|actor| actor.name.clone()).collect();
 
-    let mut actors: Vec<ActorSpec> = vec![];
-    if let Some(items) = &playbook.spec.actors {
-        actors = items.clone();
+    let patch = json!({"spec": { "actors": merge(playbook, items) }});
+    let playbook = api
+        .patch(
+            playbook.name_any().as_str(),
+            &PatchParams::apply("amp-controllers"),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Added actors {:?} for {}", names, playbook.name_any());
+
+    Ok(())
+}
+
+/// Add the actors and save the value as the JSON encoded annotation of this
+/// playbook in a single patch, so that they're never inconsistent. The patch
+/// is rejected if the playbook has been modified since it was read.
+pub async fn add_annotated<T: Serialize>(
+    client: &Client,
+    playbook: &Playbook,
+    items: Vec<ActorSpec>,
+    key: &str,
+    value: &T,
+) -> Result<()> {
+    let api: Api<Playbook> = Api::all(client.clone());
+    let names: Vec<String> = items.iter().map(|actor| actor.name.clone()).collect();
+
+    let value = to_string(value).map_err(Error::SerializationError)?;
+    let patch = json!({
+        "metadata": {
+            "resourceVersion": playbook.resource_version(),
+            "annotations": { key: value }
+        },
+        "spec": { "actors": merge(playbook, items) }
+    });
+    let playbook = api
+        .patch(
+            playbook.name_any().as_str(),
+            &PatchParams::apply("amp-controllers"),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!(
+        "Added actors {:?} and annotated {} for {}",
+        names,
+        key,
+        playbook.name_any()
+    );
+
+    Ok(())
+}
+
+/// Merge the actors onto the ones of playbook by name.
+fn merge(playbook: &Playbook, items: Vec<ActorSpec>) -> Vec<ActorSpec> {
+    let mut actors: Vec<ActorSpec> = playbook.spec.actors.clone().unwrap_or_default();
+
+    for item in items {
+        match actors.iter_mut().find(|actor| actor.name == item.name) {
+            Some(actor) => *actor = item,
+            None => actors.push(item),
+        }
     }
-    actors.push(actor);
 
-    let patch = json!({"spec": { "actors": actors }});
+    actors
+}
+
+/// Save the value as the JSON encoded annotation of this playbook.
+pub async fn annotate<T: Serialize>(client: &Client, playbook: &Playbook, key: &str, value: &T) -> Result<()> {
+    let api: Api<Playbook> = Api::all(client.clone());
+
+    let value = to_string(value).map_err(Error::SerializationError)?;
+    let patch = json!({"metadata": { "annotations": { key: value }}});
     let playbook = api
         .patch(
             playbook.name_any().as_str(),
@@ -106,7 +190,7 @@ pub async fn add(client: &Client, playbook: &Playbook, actor: ActorSpec) -> Resu
         .await
         .map_err(Error::KubeError)?;
 
-    tracing::info!("Added actor {:?} for {}", actor_name, playbook.name_any());
+    tracing::info!("Annotated {} for {}", key, playbook.name_any());
 
     Ok(())
 }
@@ -142,6 +226,34 @@ pub async fn replace_status_with(
     todo!()
 }
 
+/// Read the variables of this playbook
+pub fn variables(playbook: &Playbook) -> Result<HashMap<String, String>> {
+    annotation(playbook, VARIABLES_ANNOTATION_KEY)
+}
+
+/// Read the overrides of this playbook, keyed by actor name
+pub fn overrides(playbook: &Playbook) -> Result<HashMap<String, Value>> {
+    annotation(playbook, OVERRIDES_ANNOTATION_KEY)
+}
+
+/// Read the settings of actors in this playbook, keyed by actor name
+pub fn settings(playbook: &Playbook) -> Result<BTreeMap<String, Settings>> {
+    annotation(playbook, SETTINGS_ANNOTATION_KEY)
+}
+
+/// Check if this playbook refuses to move actors to newer commits silently
+pub fn locked(playbook: &Playbook) -> bool {
+    playbook
+        .annotations()
+        .get(LOCKED_ANNOTATION_KEY)
+        .map_or(false, |v| v == "true")
+}
+
+/// The repository for the build cache of actors, if it's configured for this playbook.
+pub fn cache_repo(playbook: &Playbook) -> Option<String> {
+    playbook.annotations().get(CACHE_REPO_ANNOTATION_KEY).cloned()
+}
+
 /// List all playbooks
 pub async fn list(client: &Client) -> Result<ObjectList<Playbook>> {
     let api: Api<Playbook> = Api::all(client.clone());
@@ -0,0 +1,214 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::core::v1::{
+    ConfigMapVolumeSource, Container, EnvVar, EnvVarSource, Pod, PodSpec, PodTemplateSpec, SecretKeySelector,
+    SecretVolumeSource, Volume, VolumeMount,
+};
+use kube::api::ListParams;
+use kube::core::ObjectMeta;
+use kube::{Api, Client, ResourceExt};
+
+use super::error::{Error, Result};
+use super::settings::{ContainerSettings, ProbeSettings};
+use super::{annotation, config_map, digest, hash, secret, settings};
+
+/// The number of pods scaled through the API, it takes precedence over the manifest.
+pub const REPLICAS_ANNOTATION_KEY: &str = "amphitheatre.app/replicas";
+
+/// The labels of the pods of actor, they're selected by its workload and Service.
+pub fn labels(actor: &Actor) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("app.kubernetes.io/name".into(), actor.name_any()),
+        ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+    ])
+}
+
+/// The latest pod of actor, none if it has no pods.
+pub async fn latest(client: &Client, actor: &Actor) -> Result<Option<Pod>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let params = ListParams::default().labels(&format!("app.kubernetes.io/name={}", actor.name_any()));
+    let pods = api.list(&params).await.map_err(Error::KubeError)?;
+
+    Ok(pods.items.into_iter().max_by_key(|pod| pod.creation_timestamp()))
+}
+
+/// The pod template of actor, shared by the workloads (Deployment or StatefulSet).
+pub fn template(actor: &Actor) -> Result<PodTemplateSpec> {
+    // Run the image pinned by digest if it's resolved, the pinned image
+    // is immutable, so it's unnecessary to pull it every time.
+    let (image, pull_policy) = match digest::of(actor) {
+        Some(image) => (image, "IfNotPresent"),
+        None => (settings::of(actor)?.image(&actor.spec), "Always"),
+    };
+
+    // The volumes are mounted with their names, they're claimed by
+    // the workload (a PVC for Deployment, or templates for StatefulSet).
+    let settings = settings::of(actor)?;
+    let mut mounts: Vec<VolumeMount> = settings
+        .deploy
+        .volumes
+        .iter()
+        .map(|volume| VolumeMount {
+            name: volume.name.clone(),
+            mount_path: volume.path.clone(),
+            ..Default::default()
+        })
+        .collect();
+
+    // The configs are mounted from their ConfigMaps as read-only directories.
+    let mut volumes = vec![];
+    for config in settings.deploy.configs.iter() {
+        let name = format!("config-{}", config.name);
+        volumes.push(Volume {
+            name: name.clone(),
+            config_map: Some(ConfigMapVolumeSource {
+                name: Some(config_map::name(actor, config)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        mounts.push(VolumeMount {
+            name,
+            mount_path: config.path.clone(),
+            read_only: Some(true),
+            ..Default::default()
+        });
+    }
+
+    // The runtime secrets are referenced as environment variables,
+    // and mounted as read-only directories if their paths are declared.
+    let mut env = actor.spec.environments().unwrap_or_default();
+    for runtime in settings.deploy.secrets.iter() {
+        let name = secret::runtime_name(&runtime.name);
+        env.extend(runtime.env.iter().map(|(variable, key)| EnvVar {
+            name: variable.clone(),
+            value_from: Some(EnvVarSource {
+                secret_key_ref: Some(SecretKeySelector {
+                    name: Some(name.clone()),
+                    key: key.clone(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }));
+
+        if let Some(path) = &runtime.path {
+            volumes.push(Volume {
+                name: format!("secret-{}", runtime.name),
+                secret: Some(SecretVolumeSource {
+                    secret_name: Some(name),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            mounts.push(VolumeMount {
+                name: format!("secret-{}", runtime.name),
+                mount_path: path.clone(),
+                read_only: Some(true),
+                ..Default::default()
+            });
+        }
+    }
+
+    let container = Container {
+        name: actor.name_any(),
+        image: Some(image),
+        image_pull_policy: Some(pull_policy.into()),
+        env: Some(env).filter(|env| !env.is_empty()),
+        ports: actor.spec.container_ports(),
+        volume_mounts: Some(mounts).filter(|mounts| !mounts.is_empty()),
+        liveness_probe: settings.deploy.probes.liveness.as_ref().map(ProbeSettings::probe),
+        readiness_probe: settings.deploy.probes.readiness.as_ref().map(ProbeSettings::probe),
+        startup_probe: settings.deploy.probes.startup.as_ref().map(ProbeSettings::probe),
+        resources: settings.deploy.resources,
+        ..Default::default()
+    };
+
+    // The sidecars are run alongside the container of actor, and the
+    // init containers are run to completion in order before them.
+    let mut containers = vec![container];
+    containers.extend(settings.deploy.sidecars.iter().map(ContainerSettings::container));
+    let init_containers: Vec<Container> = settings
+        .deploy
+        .init_containers
+        .iter()
+        .map(ContainerSettings::container)
+        .collect();
+
+    // The contents of configs and secrets are annotated on the pods, so that
+    // they're rolled when the contents changed, which are not watched by pods.
+    let mut annotations = BTreeMap::new();
+    for (key, kind) in [
+        (config_map::HASHES_ANNOTATION_KEY, "config"),
+        (secret::HASHES_ANNOTATION_KEY, "secret"),
+    ] {
+        let hashes: BTreeMap<String, String> = annotation(actor, key)?;
+        for (name, hash) in hashes {
+            annotations.insert(format!("amphitheatre.app/{}-{}", kind, name), hash);
+        }
+    }
+
+    Ok(PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            labels: Some(labels(actor)),
+            annotations: Some(annotations).filter(|annotations| !annotations.is_empty()),
+            ..Default::default()
+        }),
+        spec: Some(PodSpec {
+            init_containers: Some(init_containers).filter(|containers| !containers.is_empty()),
+            containers,
+            volumes: Some(volumes).filter(|volumes| !volumes.is_empty()),
+            ..Default::default()
+        }),
+    })
+}
+
+/// The number of pods of actor, scaled through the API or declared in the manifest,
+/// it's left to the HorizontalPodAutoscaler if the actor is autoscaled.
+pub fn replicas(actor: &Actor) -> Result<Option<i32>> {
+    let settings = settings::of(actor)?;
+    if settings.deploy.autoscaling.is_some() {
+        return Ok(None);
+    }
+
+    match actor.annotations().get(REPLICAS_ANNOTATION_KEY) {
+        Some(replicas) => Ok(replicas.parse().ok()),
+        None => Ok(settings.deploy.replicas),
+    }
+}
+
+/// The hash of the spec, settings, pinned image, replicas and the contents of
+/// configs and secrets of actor, the workload is updated when any of them changed.
+pub fn fingerprint(actor: &Actor) -> Result<String> {
+    let contents = (
+        actor.annotations().get(config_map::HASHES_ANNOTATION_KEY),
+        actor.annotations().get(secret::HASHES_ANNOTATION_KEY),
+    );
+    hash(&(
+        &actor.spec,
+        settings::of(actor)?,
+        digest::of(actor),
+        replicas(actor)?,
+        contents,
+    ))
+}
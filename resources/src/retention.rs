@@ -0,0 +1,269 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use amp_common::config::CredentialConfiguration;
+use k8s_openapi::chrono::{DateTime, Utc};
+use oci_distribution::client::{Client, ClientConfig, ClientProtocol};
+use oci_distribution::manifest::OciManifest;
+use oci_distribution::secrets::RegistryAuth;
+use oci_distribution::Reference;
+use serde::Deserialize;
+
+use super::digest::auth;
+use super::error::{Error, Result};
+
+/// The fields of image config used to tell the age of images.
+#[derive(Deserialize)]
+struct ImageConfig {
+    created: Option<DateTime<Utc>>,
+}
+
+/// The image tagged in the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Image {
+    tag: String,
+    digest: String,
+    /// The digests of the manifests referenced by the image, if it's an index.
+    manifests: Vec<String>,
+    created: Option<DateTime<Utc>>,
+}
+
+/// Prune the tags of repository, keeps the `keep` most recently created ones
+/// and the protected ones, returns the pruned tags. The registries in
+/// `insecure_registries` are accessed over plain HTTP.
+///
+/// The manifest is deleted by its digest, so the registry must allow deletes,
+/// e.g. `REGISTRY_STORAGE_DELETE_ENABLED=true` for the Docker Registry.
+pub async fn prune(
+    configuration: &CredentialConfiguration,
+    insecure_registries: &[String],
+    repository: &str,
+    keep: usize,
+    protected: &HashSet<String>,
+) -> Result<Vec<String>> {
+    let reference: Reference = format!("{}:latest", repository)
+        .parse()
+        .map_err(|e| Error::RegistryError(format!("{}", e)))?;
+    let auth = auth(configuration, &reference);
+    let mut client = Client::new(ClientConfig {
+        protocol: ClientProtocol::HttpsExcept(insecure_registries.to_vec()),
+        ..Default::default()
+    });
+
+    let tags = client
+        .list_tags(&reference, &auth, None, None)
+        .await
+        .map_err(|e| Error::RegistryError(e.to_string()))?
+        .tags;
+
+    let mut images = vec![];
+    for tag in tags {
+        let image = Reference::with_tag(reference.registry().into(), reference.repository().into(), tag.clone());
+        let (manifest, digest) = match client.pull_manifest(&image, &auth).await {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                tracing::warn!("Failed to fetch the manifest of {}: {}", image, err);
+                continue;
+            }
+        };
+        let manifests = match manifest {
+            OciManifest::ImageIndex(index) => index.manifests.into_iter().map(|entry| entry.digest).collect(),
+            OciManifest::Image(_) => vec![],
+        };
+
+        // The protected ones are kept regardless of their ages.
+        let created = match protected.contains(&tag) {
+            true => None,
+            false => match client.pull_manifest_and_config(&image, &auth).await {
+                Ok((_, _, config)) => serde_json::from_str::<ImageConfig>(&config)
+                    .ok()
+                    .and_then(|config| config.created),
+                Err(_) => None,
+            },
+        };
+
+        images.push(Image {
+            tag,
+            digest,
+            manifests,
+            created,
+        });
+    }
+
+    let scheme = scheme(insecure_registries, &reference);
+    let mut pruned = vec![];
+    for image in select(images, keep, protected) {
+        delete(scheme, &reference, &auth, &image.digest).await?;
+        tracing::info!("Pruned the image {}:{} ({})", repository, image.tag, image.digest);
+        pruned.push(image.tag);
+    }
+
+    Ok(pruned)
+}
+
+/// Select the images to delete, keeps the `keep` most recently created ones
+/// besides the protected ones. The digests of the kept images, and the ones
+/// referenced by the kept indexes, are never deleted even if they are tagged
+/// with other names. Each digest is selected once.
+fn select(images: Vec<Image>, keep: usize, protected: &HashSet<String>) -> Vec<Image> {
+    let (kept, mut candidates): (Vec<Image>, Vec<Image>) =
+        images.into_iter().partition(|image| protected.contains(&image.tag));
+
+    // The most recently created ones come first, the unknown ones are the oldest.
+    candidates.sort_by_key(|image| Reverse(image.created));
+    let rest = candidates.split_off(keep.min(candidates.len()));
+
+    let mut keeps = HashSet::new();
+    for image in kept.iter().chain(candidates.iter()) {
+        keeps.insert(image.digest.clone());
+        keeps.extend(image.manifests.iter().cloned());
+    }
+
+    rest.into_iter()
+        .filter(|image| keeps.insert(image.digest.clone()))
+        .collect()
+}
+
+/// The scheme of the registry API, plain HTTP for the insecure registries.
+fn scheme(insecure_registries: &[String], reference: &Reference) -> &'static str {
+    let insecure = insecure_registries
+        .iter()
+        .any(|registry| registry == reference.registry() || registry == reference.resolve_registry());
+
+    match insecure {
+        true => "http",
+        false => "https",
+    }
+}
+
+/// Delete the manifest by its digest via the registry API.
+async fn delete(scheme: &str, reference: &Reference, auth: &RegistryAuth, digest: &str) -> Result<()> {
+    let url = format!(
+        "{}://{}/v2/{}/manifests/{}",
+        scheme,
+        reference.resolve_registry(),
+        reference.repository(),
+        digest
+    );
+
+    let mut request = reqwest::Client::new().delete(&url);
+    if let RegistryAuth::Basic(username, password) = auth {
+        request = request.basic_auth(username, Some(password));
+    }
+
+    let response = request.send().await.map_err(|e| Error::RegistryError(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(Error::RegistryError(format!(
+            "Failed to delete {}: {}",
+            url,
+            response.status()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::TimeZone;
+
+    use super::*;
+
+    fn image(tag: &str, digest: &str, day: Option<u32>) -> Image {
+        Image {
+            tag: tag.into(),
+            digest: digest.into(),
+            manifests: vec![],
+            created: day.map(|day| Utc.with_ymd_and_hms(2023, 3, day, 0, 0, 0).unwrap()),
+        }
+    }
+
+    fn tags(images: Vec<Image>) -> Vec<String> {
+        images.into_iter().map(|image| image.tag).collect()
+    }
+
+    #[test]
+    fn test_select_keeps_the_most_recent() {
+        let images = vec![
+            image("a", "sha256:a", Some(1)),
+            image("c", "sha256:c", Some(3)),
+            image("unknown", "sha256:u", None),
+            image("b", "sha256:b", Some(2)),
+        ];
+
+        assert_eq!(tags(select(images, 2, &HashSet::new())), vec!["a", "unknown"]);
+    }
+
+    #[test]
+    fn test_select_keeps_the_protected() {
+        let images = vec![
+            image("old", "sha256:old", Some(1)),
+            image("b", "sha256:b", Some(2)),
+            image("c", "sha256:c", Some(3)),
+        ];
+        let protected = HashSet::from(["old".to_string()]);
+
+        // The protected ones are not counted in the most recent ones.
+        assert_eq!(tags(select(images, 1, &protected)), vec!["b"]);
+    }
+
+    #[test]
+    fn test_select_keeps_the_shared_digests() {
+        let images = vec![
+            image("current", "sha256:a", None),
+            image("alias", "sha256:a", Some(1)),
+            image("recent", "sha256:b", Some(3)),
+            image("old", "sha256:b", Some(2)),
+        ];
+        let protected = HashSet::from(["current".to_string()]);
+
+        assert!(select(images, 1, &protected).is_empty());
+    }
+
+    #[test]
+    fn test_select_keeps_the_manifests_of_index() {
+        let mut index = image("v2", "sha256:index", None);
+        index.manifests = vec!["sha256:amd64".into(), "sha256:arm64".into()];
+        let images = vec![
+            index,
+            image("v2-amd64", "sha256:amd64", Some(1)),
+            image("v2-arm64", "sha256:arm64", Some(1)),
+            image("v1", "sha256:v1", Some(2)),
+        ];
+        let protected = HashSet::from(["v2".to_string()]);
+
+        assert_eq!(tags(select(images, 0, &protected)), vec!["v1"]);
+    }
+
+    #[test]
+    fn test_select_each_digest_once() {
+        let images = vec![
+            image("a", "sha256:a", Some(1)),
+            image("b", "sha256:a", Some(2)),
+            image("c", "sha256:c", Some(3)),
+        ];
+
+        assert_eq!(tags(select(images, 0, &HashSet::new())).len(), 2);
+    }
+
+    #[test]
+    fn test_scheme() {
+        let reference: Reference = "registry.local:5000/app:v1".parse().unwrap();
+        assert_eq!(scheme(&["registry.local:5000".into()], &reference), "http");
+        assert_eq!(scheme(&[], &reference), "https");
+    }
+}
@@ -16,15 +16,136 @@ use std::collections::BTreeMap;
 
 use amp_common::config::{Credential, Scheme};
 use amp_common::docker::DockerConfig;
+use amp_common::schema::Actor;
 use k8s_openapi::api::core::v1::Secret;
 use k8s_openapi::ByteString;
-use kube::api::{Patch, PatchParams};
+use kube::api::{DeleteParams, ListParams, Patch, PatchParams};
 use kube::core::ObjectMeta;
 use kube::{Api, Client, ResourceExt};
 use serde_json::to_string;
 use url::Url;
 
 use super::error::{Error, Result};
+use super::{hash, settings};
+
+/// The label of runtime secrets, it's `platform` for the ones copied
+/// from the namespace of Amphitheatre, or `playbook` for the ones set by API.
+pub const SCOPE_LABEL_KEY: &str = "amphitheatre.app/secret-scope";
+
+/// The hashes of the data of runtime secrets keyed by their names (in JSON), they're
+/// annotated on the pods, so that the pods are rolled when the secrets changed.
+pub const HASHES_ANNOTATION_KEY: &str = "amphitheatre.app/secret-hashes";
+
+/// The max length of the names of runtime secrets, so that the name of
+/// their volumes in pods (`secret-{name}`) is a DNS label as well.
+const MAX_NAME_LENGTH: usize = 56;
+
+/// The name of Secret for the runtime secret in the namespace of playbook.
+pub fn runtime_name(name: &str) -> String {
+    format!("amp-secrets-{}", name)
+}
+
+/// Check if the name of runtime secret is a DNS label (RFC 1123), which
+/// consists of lowercase alphanumerics or `-`, and starts and ends with an
+/// alphanumeric, it's short enough for the names derived from it.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// The hashes of the data of runtime secrets referenced by actor, keyed
+/// by their names, the ones not found are skipped.
+pub async fn hashes(client: &Client, actor: &Actor) -> Result<BTreeMap<String, String>> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+
+    let mut hashes = BTreeMap::new();
+    for runtime in settings::of(actor)?.deploy.secrets {
+        if let Some(secret) = read(client, &namespace, &runtime_name(&runtime.name)).await? {
+            hashes.insert(runtime.name, hash(&secret.data)?);
+        }
+    }
+
+    Ok(hashes)
+}
+
+/// Check if the runtime secret (the name of Secret) is referenced by actor.
+pub fn references(actor: &Actor, secret: &str) -> bool {
+    settings::of(actor).map_or(false, |settings| {
+        settings
+            .deploy
+            .secrets
+            .iter()
+            .any(|runtime| runtime_name(&runtime.name) == secret)
+    })
+}
+
+/// Copy the runtime secrets of platform from the namespace of Amphitheatre into
+/// the namespace of playbook, the ones set for the playbook are never overwritten.
+/// The copies of the ones no longer declared or found are deleted.
+pub async fn sync(client: &Client, source: &str, namespace: &str, names: &[String]) -> Result<()> {
+    let mut synced = vec![];
+    for name in names.iter() {
+        let secret = match read(client, source, name).await? {
+            Some(secret) => secret,
+            None => {
+                tracing::warn!("The runtime secret {} is not found in {}", name, source);
+                continue;
+            }
+        };
+
+        if let Some(found) = read(client, namespace, &runtime_name(name)).await? {
+            if found.labels().get(SCOPE_LABEL_KEY).map(String::as_str) == Some("playbook") {
+                continue;
+            }
+        }
+
+        create(
+            client,
+            namespace,
+            runtime(name, "platform", secret.data.unwrap_or_default()),
+        )
+        .await?;
+        synced.push(runtime_name(name));
+    }
+
+    let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let params = ListParams::default().labels(&format!("{}=platform", SCOPE_LABEL_KEY));
+    for secret in api.list(&params).await.map_err(Error::KubeError)? {
+        let name = secret.name_any();
+        if !synced.contains(&name) {
+            api.delete(&name, &DeleteParams::default())
+                .await
+                .map_err(Error::KubeError)?;
+            tracing::info!("Deleted Secret {:?}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// The runtime secret of the scope (`platform` or `playbook`).
+pub fn runtime(name: &str, scope: &str, data: BTreeMap<String, ByteString>) -> Secret {
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(runtime_name(name)),
+            labels: Some(BTreeMap::from([
+                (SCOPE_LABEL_KEY.into(), scope.into()),
+                ("app.kubernetes.io/managed-by".into(), "Amphitheatre".into()),
+            ])),
+            ..Default::default()
+        },
+        type_: Some("Opaque".into()),
+        data: Some(data),
+        ..Default::default()
+    }
+}
 
 pub async fn create_registry_secret(client: &Client, namespace: &str, config: DockerConfig) -> Result<Secret> {
     let resource = Secret {
@@ -112,3 +233,28 @@ pub async fn create(client: &Client, namespace: &str, resource: Secret) -> Resul
     tracing::info!("Added Secret {:?}", secret.name_any());
     Ok(secret)
 }
+
+pub async fn read(client: &Client, namespace: &str, name: &str) -> Result<Option<Secret>> {
+    let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    api.get_opt(name).await.map_err(Error::KubeError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_name() {
+        assert!(valid_name("database"));
+        assert!(valid_name("db-1"));
+        assert!(valid_name(&"a".repeat(MAX_NAME_LENGTH)));
+
+        assert!(!valid_name(""));
+        assert!(!valid_name("-database"));
+        assert!(!valid_name("database-"));
+        assert!(!valid_name("Database"));
+        assert!(!valid_name("data_base"));
+        assert!(!valid_name("data.base"));
+        assert!(!valid_name(&"a".repeat(MAX_NAME_LENGTH + 1)));
+    }
+}
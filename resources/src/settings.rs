@@ -0,0 +1,491 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use amp_common::schema::{Actor, ActorSpec};
+use k8s_openapi::api::core::v1::{
+    Container, ContainerPort, EnvVar, ExecAction, HTTPGetAction, Probe, ResourceRequirements, TCPSocketAction,
+    VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use serde::{Deserialize, Serialize};
+
+use super::annotation;
+use super::builder::Backend;
+use super::error::{Error, Result};
+
+/// The settings of actor (in JSON), on Playbook they're keyed by actor name.
+pub const SETTINGS_ANNOTATION_KEY: &str = "amphitheatre.app/settings";
+
+/// The default timeout of builds, in seconds.
+const DEFAULT_BUILD_TIMEOUT: u64 = 3600;
+
+/// The settings declared in the actor manifest (`.amp.toml`) besides the
+/// fields of `ActorSpec`, they're carried to the Actor with annotation.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub build: BuildSettings,
+    pub deploy: DeploySettings,
+}
+
+impl Settings {
+    /// Whether the actor runs a prebuilt image, it's never built.
+    pub fn prebuilt(&self) -> bool {
+        self.deploy.image.is_some()
+    }
+
+    /// The image reference to deploy, the prebuilt image takes
+    /// precedence over the one built from source.
+    pub fn image(&self, spec: &ActorSpec) -> String {
+        self.deploy.image.clone().unwrap_or_else(|| spec.docker_tag())
+    }
+}
+
+/// The `[build]` table of manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct BuildSettings {
+    /// The build is failed if it takes longer than this, in seconds.
+    pub timeout: Option<u64>,
+    /// The backend for building the image, overrides the platform default.
+    pub backend: Option<Backend>,
+    /// The target platforms of a multi-arch image, e.g. `linux/amd64`, `linux/arm64`.
+    pub platforms: Vec<String>,
+    /// The repository for the build cache, overrides the platform default.
+    pub cache_repo: Option<String>,
+    /// The keys of Secret `amp-build-secrets` in the namespace of Amphitheatre,
+    /// they're exposed to the build without being inlined into its spec.
+    pub secrets: Vec<String>,
+}
+
+impl BuildSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(DEFAULT_BUILD_TIMEOUT))
+    }
+}
+
+/// The `[deploy]` table of manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct DeploySettings {
+    /// The reference of a prebuilt image, e.g. `postgres:15`, for the
+    /// third-party characters that have no source to build.
+    pub image: Option<String>,
+    /// The kind of workload runs the actor.
+    pub kind: Workload,
+    /// The persistent volumes mounted into the container.
+    pub volumes: Vec<VolumeSettings>,
+    /// The number of pods, it's one if absent.
+    pub replicas: Option<i32>,
+    /// The compute resources (CPU & Memory) of the container, e.g.
+    /// `[deploy.resources.requests]` and `[deploy.resources.limits]`.
+    pub resources: Option<ResourceRequirements>,
+    /// The health probes of the container.
+    pub probes: ProbesSettings,
+    /// The ports exposed publicly with Ingress, `[[deploy.exposes]]`.
+    pub exposes: Vec<ExposeSettings>,
+    /// Scale the pods automatically by their utilization, the `replicas` is ignored if it's present.
+    pub autoscaling: Option<AutoscalingSettings>,
+    /// The containers run alongside the actor, e.g. proxies or log shippers, `[[deploy.sidecars]]`.
+    pub sidecars: Vec<ContainerSettings>,
+    /// The containers run to completion before the actor starts, e.g. migrations
+    /// or waiting for partners, in order, `[[deploy.init_containers]]`.
+    pub init_containers: Vec<ContainerSettings>,
+    /// The files of repository mounted into the container, `[[deploy.configs]]`.
+    pub configs: Vec<ConfigSettings>,
+    /// The runtime secrets referenced by the container, `[[deploy.secrets]]`.
+    pub secrets: Vec<SecretSettings>,
+}
+
+/// The `[[deploy.secrets]]` tables of manifest, a secret of the platform or
+/// playbook, e.g. `{ name = "database", env = { DATABASE_URL = "url" } }`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SecretSettings {
+    /// The name of secret, the playbook ones take precedence over the platform ones.
+    pub name: String,
+    /// The environment variables of container, keyed by their names, the values are the keys of secret.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The directory where the keys of secret are mounted as files, if it's present.
+    pub path: Option<String>,
+}
+
+/// The `[[deploy.configs]]` tables of manifest, the files are fetched at the
+/// revision of actor when it's deployed and materialized as a ConfigMap, which
+/// is mounted as a directory, e.g.
+/// `{ name = "nginx", path = "/etc/nginx/conf.d", files = ["nginx/default.conf"] }`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ConfigSettings {
+    /// The name of config, unique in the actor.
+    pub name: String,
+    /// The directory where the files are mounted in the container, by their base names.
+    pub path: String,
+    /// The paths of the (UTF-8) files, relative to the actor in repository.
+    pub files: Vec<String>,
+}
+
+/// The additional containers of manifest, they're run in the pods of actor.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ContainerSettings {
+    /// The name of container, unique in the pod.
+    pub name: String,
+    /// The image of container, e.g. `envoyproxy/envoy:v1.26.1`.
+    pub image: String,
+    /// The entrypoint of container, the ENTRYPOINT of image is used if absent.
+    pub command: Option<Vec<String>>,
+    /// The arguments to the entrypoint, the CMD of image is used if absent.
+    pub args: Option<Vec<String>>,
+    /// The environment variables of container.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The ports exposed by container.
+    #[serde(default)]
+    pub ports: Vec<i32>,
+    /// The volumes of actor mounted into container, `{ volume = "data", path = "/data" }`.
+    #[serde(default)]
+    pub mounts: Vec<MountSettings>,
+    /// The compute resources (CPU & Memory) of container.
+    pub resources: Option<ResourceRequirements>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct MountSettings {
+    /// The name of the volume declared in `[[deploy.volumes]]`.
+    pub volume: String,
+    /// Where the volume is mounted in container.
+    pub path: String,
+}
+
+impl ContainerSettings {
+    /// The Container for these settings.
+    pub fn container(&self) -> Container {
+        let env: Vec<EnvVar> = self
+            .env
+            .iter()
+            .map(|(name, value)| EnvVar {
+                name: name.clone(),
+                value: Some(value.clone()),
+                ..Default::default()
+            })
+            .collect();
+        let ports: Vec<ContainerPort> = self
+            .ports
+            .iter()
+            .map(|port| ContainerPort {
+                container_port: *port,
+                ..Default::default()
+            })
+            .collect();
+        let mounts: Vec<VolumeMount> = self
+            .mounts
+            .iter()
+            .map(|mount| VolumeMount {
+                name: mount.volume.clone(),
+                mount_path: mount.path.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        Container {
+            name: self.name.clone(),
+            image: Some(self.image.clone()),
+            command: self.command.clone(),
+            args: self.args.clone(),
+            env: Some(env).filter(|env| !env.is_empty()),
+            ports: Some(ports).filter(|ports| !ports.is_empty()),
+            volume_mounts: Some(mounts).filter(|mounts| !mounts.is_empty()),
+            resources: self.resources.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The `[deploy.autoscaling]` table of manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct AutoscalingSettings {
+    /// The lower limit of the number of pods, it's one if absent.
+    pub min_replicas: Option<i32>,
+    /// The upper limit of the number of pods.
+    pub max_replicas: i32,
+    /// The target average CPU utilization (in percent of the requested CPU).
+    pub cpu: Option<i32>,
+    /// The target average memory utilization (in percent of the requested memory).
+    pub memory: Option<i32>,
+}
+
+/// The `[[deploy.exposes]]` tables of manifest.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct ExposeSettings {
+    /// The port of Service to expose.
+    pub port: i32,
+    /// The first label of hostname, it's the actor name if absent.
+    pub host: Option<String>,
+    /// The path prefix routed to the port, the default is `/`.
+    pub path: Option<String>,
+}
+
+/// The `[deploy.probes]` table of manifest.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ProbesSettings {
+    /// The container is restarted if it fails.
+    pub liveness: Option<ProbeSettings>,
+    /// The pod receives no traffic from Services until it succeeds.
+    pub readiness: Option<ProbeSettings>,
+    /// The other probes are disabled until it succeeds, for the slow starting containers.
+    pub startup: Option<ProbeSettings>,
+}
+
+/// A probe of manifest, e.g. `[deploy.probes.readiness]`, one of `http`, `tcp` or `exec`
+/// is checked, the thresholds are the defaults of Kubernetes if absent.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct ProbeSettings {
+    /// Send a HTTP GET request, e.g. `{ path = "/healthz", port = 8080 }`.
+    pub http: Option<HttpProbeSettings>,
+    /// Open a TCP connection to the port.
+    pub tcp: Option<i32>,
+    /// Run the command in the container, it's healthy if the command exits with zero.
+    pub exec: Option<Vec<String>>,
+    /// The seconds after the container has started before the probe is initiated.
+    pub initial_delay: Option<i32>,
+    /// How often (in seconds) to perform the probe.
+    pub period: Option<i32>,
+    /// The seconds after which the probe times out.
+    pub timeout: Option<i32>,
+    /// The consecutive successes for the probe to be considered successful after having failed.
+    pub success_threshold: Option<i32>,
+    /// The consecutive failures for the probe to be considered failed after having succeeded.
+    pub failure_threshold: Option<i32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HttpProbeSettings {
+    pub path: String,
+    pub port: i32,
+}
+
+impl ProbeSettings {
+    /// The Probe of container for these settings.
+    pub fn probe(&self) -> Probe {
+        Probe {
+            http_get: self.http.as_ref().map(|http| HTTPGetAction {
+                path: Some(http.path.clone()),
+                port: IntOrString::Int(http.port),
+                ..Default::default()
+            }),
+            tcp_socket: self.tcp.map(|port| TCPSocketAction {
+                port: IntOrString::Int(port),
+                ..Default::default()
+            }),
+            exec: self.exec.as_ref().map(|command| ExecAction {
+                command: Some(command.clone()),
+            }),
+            initial_delay_seconds: self.initial_delay,
+            period_seconds: self.period,
+            timeout_seconds: self.timeout,
+            success_threshold: self.success_threshold,
+            failure_threshold: self.failure_threshold,
+            ..Default::default()
+        }
+    }
+}
+
+/// The kinds of workload, the StatefulSet is for the stateful actors
+/// which need stable identities and per-replica storage, e.g. databases.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Workload {
+    #[default]
+    Deployment,
+    StatefulSet,
+}
+
+/// The `[[deploy.volumes]]` tables of manifest.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct VolumeSettings {
+    /// The name of volume, unique in the actor.
+    pub name: String,
+    /// Where the volume is mounted in the container.
+    pub path: String,
+    /// The requested size of volume, e.g. `1Gi`.
+    pub size: String,
+    /// The storage class of volume, the default one of cluster is used if absent.
+    pub storage_class: Option<String>,
+    /// The access mode of volume, the default is `ReadWriteOnce`.
+    pub access_mode: Option<String>,
+    /// What happens to the data of volume when the playbook is deleted.
+    #[serde(default)]
+    pub policy: VolumePolicy,
+}
+
+/// The reclaim policies of volumes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumePolicy {
+    /// The data is deleted along with the playbook.
+    #[default]
+    Delete,
+    /// The data is retained in the persistent volume after the playbook is deleted.
+    Retain,
+}
+
+/// Parse the settings from the content of manifest.
+pub fn parse(content: &str) -> Result<Settings> {
+    toml::from_str(content).map_err(Error::TomlParseError)
+}
+
+/// Read the settings of this actor.
+pub fn of(actor: &Actor) -> Result<Settings> {
+    annotation(actor, SETTINGS_ANNOTATION_KEY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let settings = parse("").unwrap();
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.build.timeout(), Duration::from_secs(DEFAULT_BUILD_TIMEOUT));
+    }
+
+    #[test]
+    fn test_parse_build_timeout() {
+        let settings = parse("[build]\ntimeout = 600").unwrap();
+        assert_eq!(settings.build.timeout(), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn test_parse_prebuilt() {
+        assert!(!parse("").unwrap().prebuilt());
+
+        let settings = parse("[deploy]\nimage = \"postgres:15\"").unwrap();
+        assert!(settings.prebuilt());
+        assert_eq!(settings.deploy.image.as_deref(), Some("postgres:15"));
+    }
+
+    #[test]
+    fn test_parse_platforms() {
+        let settings = parse("[build]\nplatforms = [\"linux/amd64\", \"linux/arm64\"]").unwrap();
+        assert_eq!(settings.build.platforms, vec!["linux/amd64", "linux/arm64"]);
+    }
+
+    #[test]
+    fn test_parse_workload() {
+        assert_eq!(parse("").unwrap().deploy.kind, Workload::Deployment);
+        assert_eq!(
+            parse("[deploy]\nkind = \"statefulset\"").unwrap().deploy.kind,
+            Workload::StatefulSet
+        );
+        assert!(parse("[deploy]\nkind = \"daemonset\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_volumes() {
+        let content = r#"
+            [[deploy.volumes]]
+            name = "data"
+            path = "/var/lib/postgresql/data"
+            size = "1Gi"
+            policy = "retain"
+
+            [[deploy.volumes]]
+            name = "cache"
+            path = "/cache"
+            size = "100Mi"
+        "#;
+        let volumes = parse(content).unwrap().deploy.volumes;
+
+        assert_eq!(volumes[0].policy, VolumePolicy::Retain);
+        assert_eq!(volumes[0].access_mode, None);
+        assert_eq!(volumes[1].policy, VolumePolicy::Delete);
+        assert!(parse("[[deploy.volumes]]\nname = \"data\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_replicas() {
+        assert_eq!(parse("").unwrap().deploy.replicas, None);
+        assert_eq!(parse("[deploy]\nreplicas = 3").unwrap().deploy.replicas, Some(3));
+    }
+
+    #[test]
+    fn test_probe() {
+        let settings = ProbeSettings {
+            http: Some(HttpProbeSettings {
+                path: "/healthz".into(),
+                port: 8080,
+            }),
+            period: Some(10),
+            ..Default::default()
+        };
+        let probe = settings.probe();
+
+        let http = probe.http_get.unwrap();
+        assert_eq!(http.path.as_deref(), Some("/healthz"));
+        assert_eq!(http.port, IntOrString::Int(8080));
+        assert_eq!(probe.period_seconds, Some(10));
+        assert!(probe.tcp_socket.is_none());
+        assert!(probe.exec.is_none());
+    }
+
+    #[test]
+    fn test_parse_exposes() {
+        let content = "[[deploy.exposes]]\nport = 8080\n[[deploy.exposes]]\nport = 9090\nhost = \"admin\"";
+        let exposes = parse(content).unwrap().deploy.exposes;
+
+        assert_eq!(exposes[0].port, 8080);
+        assert_eq!(exposes[0].host, None);
+        assert_eq!(exposes[1].host.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn test_container() {
+        let settings = ContainerSettings {
+            name: "proxy".into(),
+            image: "envoyproxy/envoy:v1.26.1".into(),
+            command: None,
+            args: None,
+            env: BTreeMap::from([("LEVEL".into(), "debug".into())]),
+            ports: vec![],
+            mounts: vec![MountSettings {
+                volume: "data".into(),
+                path: "/data".into(),
+            }],
+            resources: None,
+        };
+        let container = settings.container();
+
+        assert_eq!(container.name, "proxy");
+        assert_eq!(container.image.as_deref(), Some("envoyproxy/envoy:v1.26.1"));
+        assert_eq!(container.env.unwrap()[0].value.as_deref(), Some("debug"));
+        assert!(container.ports.is_none());
+        assert_eq!(container.volume_mounts.unwrap()[0].mount_path, "/data");
+    }
+
+    #[test]
+    fn test_parse_secrets() {
+        let content = "[[deploy.secrets]]\nname = \"database\"\nenv = { DATABASE_URL = \"url\" }";
+        let secrets = parse(content).unwrap().deploy.secrets;
+
+        assert_eq!(secrets[0].name, "database");
+        assert_eq!(secrets[0].env["DATABASE_URL"], "url");
+        assert_eq!(secrets[0].path, None);
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2023 The Amphitheatre Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use amp_common::schema::Actor;
+use k8s_openapi::api::apps::v1::{StatefulSet, StatefulSetSpec};
+use k8s_openapi::api::core::v1::{PersistentVolumeClaim, Service, ServiceSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::api::{DeleteParams, Patch, PatchParams, PostParams, PropagationPolicy};
+use kube::core::ObjectMeta;
+use kube::runtime::wait::{await_condition, conditions};
+use kube::{Api, Client, Resource, ResourceExt};
+use tokio::time::timeout;
+
+use super::error::{Error, Result};
+use super::{hash, persistent_volume_claim, pod, settings, LAST_APPLIED_HASH_KEY};
+
+/// The hash of the volume claim templates, they can't be updated once created.
+const VOLUME_CLAIMS_HASH_KEY: &str = "amphitheatre.app/volume-claims-hash";
+
+/// How long to wait for the StatefulSet to be deleted, when it's recreated.
+const DELETION_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub async fn exists(client: &Client, actor: &Actor) -> Result<bool> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    Ok(api.get_opt(&name).await.map_err(Error::KubeError)?.is_some())
+}
+
+pub async fn create(client: &Client, actor: &Actor) -> Result<StatefulSet> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace.as_str());
+
+    apply_headless_service(client, actor).await?;
+
+    let resource = new(actor)?;
+    tracing::debug!("The StatefulSet resource:\n {:?}\n", resource);
+
+    let stateful_set = api
+        .create(&PostParams::default(), &resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Created StatefulSet: {}", stateful_set.name_any());
+    Ok(stateful_set)
+}
+
+pub async fn update(client: &Client, actor: &Actor) -> Result<StatefulSet> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    let mut stateful_set = api.get(&name).await.map_err(Error::KubeError)?;
+    tracing::debug!("The StatefulSet {} already exists: {:?}", &name, stateful_set);
+
+    let expected_hash = pod::fingerprint(actor)?;
+    let found_hash: String = stateful_set
+        .annotations()
+        .get(LAST_APPLIED_HASH_KEY)
+        .map_or("".into(), |v| v.into());
+
+    if found_hash != expected_hash {
+        apply_headless_service(client, actor).await?;
+
+        let resource = new(actor)?;
+        tracing::debug!("The updating StatefulSet resource:\n {:?}\n", resource);
+
+        // The volume claim templates are immutable, recreate it if they have changed.
+        let claims_hash = resource.annotations().get(VOLUME_CLAIMS_HASH_KEY);
+        if stateful_set.annotations().get(VOLUME_CLAIMS_HASH_KEY) != claims_hash {
+            return recreate(&api, stateful_set, &resource).await;
+        }
+
+        stateful_set = api
+            .patch(
+                &name,
+                &PatchParams::apply("amp-controllers").force(),
+                &Patch::Apply(&resource),
+            )
+            .await
+            .map_err(Error::KubeError)?;
+
+        tracing::info!("Updated StatefulSet: {}", stateful_set.name_any());
+    }
+
+    Ok(stateful_set)
+}
+
+/// Delete the StatefulSet of actor and its headless Service, if they exist.
+pub async fn delete(client: &Client, actor: &Actor) -> Result<()> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = actor.name_any();
+
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted StatefulSet: {}", name);
+    }
+
+    let api: Api<Service> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = headless_service_name(actor);
+    if api.get_opt(&name).await.map_err(Error::KubeError)?.is_some() {
+        api.delete(&name, &DeleteParams::default())
+            .await
+            .map_err(Error::KubeError)?;
+        tracing::info!("Deleted Service: {}", name);
+    }
+
+    Ok(())
+}
+
+/// Whether the rollout of the StatefulSet has completed, all the replicas
+/// are updated to the latest revision and ready.
+pub async fn ready(client: &Client, actor: &Actor) -> Result<bool> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace.as_str());
+
+    let stateful_set = match api.get_opt(&actor.name_any()).await.map_err(Error::KubeError)? {
+        Some(stateful_set) => stateful_set,
+        None => return Ok(false),
+    };
+    let replicas = stateful_set.spec.as_ref().and_then(|spec| spec.replicas).unwrap_or(1);
+    let status = stateful_set.status.unwrap_or_default();
+
+    Ok(status.observed_generation >= stateful_set.metadata.generation
+        && status.update_revision == status.current_revision
+        && status.updated_replicas.unwrap_or_default() >= replicas
+        && status.ready_replicas.unwrap_or_default() >= replicas)
+}
+
+fn new(actor: &Actor) -> Result<StatefulSet> {
+    let name = actor.name_any();
+
+    let owner_reference = actor.controller_owner_ref(&()).unwrap();
+    let labels = pod::labels(actor);
+
+    // Each replica claims its own volumes from the templates,
+    // they're mounted into the container with the same names.
+    let claims: Vec<PersistentVolumeClaim> = settings::of(actor)?
+        .deploy
+        .volumes
+        .iter()
+        .map(|volume| PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(volume.name.clone()),
+                labels: Some(persistent_volume_claim::labels(actor, volume)),
+                ..Default::default()
+            },
+            spec: Some(persistent_volume_claim::spec(volume)),
+            ..Default::default()
+        })
+        .collect();
+
+    let annotations = BTreeMap::from([
+        (LAST_APPLIED_HASH_KEY.into(), pod::fingerprint(actor)?),
+        (VOLUME_CLAIMS_HASH_KEY.into(), hash(&claims)?),
+    ]);
+
+    let resource = StatefulSet {
+        metadata: ObjectMeta {
+            name: Some(name),
+            owner_references: Some(vec![owner_reference]),
+            labels: Some(labels.clone()),
+            annotations: Some(annotations),
+            ..Default::default()
+        },
+        spec: Some(StatefulSetSpec {
+            service_name: headless_service_name(actor),
+            replicas: pod::replicas(actor)?,
+            selector: LabelSelector {
+                match_labels: Some(labels),
+                ..Default::default()
+            },
+            template: pod::template(actor)?,
+            volume_claim_templates: Some(claims).filter(|claims| !claims.is_empty()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    Ok(resource)
+}
+
+/// Recreate the StatefulSet, its pods and their volumes are orphaned, then
+/// adopted by the new one, the volumes claimed before are kept as they are.
+async fn recreate(api: &Api<StatefulSet>, current: StatefulSet, resource: &StatefulSet) -> Result<StatefulSet> {
+    let name = current.name_any();
+    let params = DeleteParams {
+        propagation_policy: Some(PropagationPolicy::Orphan),
+        ..Default::default()
+    };
+    api.delete(&name, &params).await.map_err(Error::KubeError)?;
+
+    // The StatefulSet is kept until its dependents are orphaned, wait for it,
+    // else, leave it to the next reconciliation once it's gone.
+    let uid = current.uid().unwrap_or_default();
+    let deleted = await_condition(api.clone(), &name, conditions::is_deleted(&uid));
+    if !matches!(timeout(DELETION_TIMEOUT, deleted).await, Ok(Ok(_))) {
+        tracing::warn!("The StatefulSet {} is still being deleted, recreate it later", name);
+        return Ok(current);
+    }
+
+    let stateful_set = api
+        .create(&PostParams::default(), resource)
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Recreated StatefulSet: {}", stateful_set.name_any());
+    Ok(stateful_set)
+}
+
+/// The name of the headless Service governs the network identities of StatefulSet.
+#[inline]
+fn headless_service_name(actor: &Actor) -> String {
+    format!("{}-headless", actor.name_any())
+}
+
+/// Create or update the headless Service, the pods of StatefulSet
+/// are addressed by `{pod}.{service}.{namespace}.svc`.
+async fn apply_headless_service(client: &Client, actor: &Actor) -> Result<Service> {
+    let namespace = actor
+        .namespace()
+        .ok_or_else(|| Error::MissingObjectKey(".metadata.namespace"))?;
+    let api: Api<Service> = Api::namespaced(client.clone(), namespace.as_str());
+    let name = headless_service_name(actor);
+
+    let labels = pod::labels(actor);
+    let resource = Service {
+        metadata: ObjectMeta {
+            name: Some(name.clone()),
+            owner_references: Some(vec![actor.controller_owner_ref(&()).unwrap()]),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            cluster_ip: Some("None".into()),
+            selector: Some(labels),
+            ports: actor.spec.service_ports(),
+            publish_not_ready_addresses: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let service = api
+        .patch(
+            &name,
+            &PatchParams::apply("amp-controllers").force(),
+            &Patch::Apply(&resource),
+        )
+        .await
+        .map_err(Error::KubeError)?;
+
+    tracing::info!("Applied headless Service: {}", service.name_any());
+    Ok(service)
+}